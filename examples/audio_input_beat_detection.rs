@@ -48,7 +48,7 @@ fn main() {
     };
     let strategy = select_strategy();
     let on_beat = |info| {
-        println!("Found beat at {:?}ms", info);
+        println!("Found {}", info);
     };
     let handle =
         beat_detector::record::start_listening(on_beat, Some(dev), strategy, recording).unwrap();
@@ -42,7 +42,7 @@ fn main() {
     let dev = select_input_device();
     let strategy = select_strategy();
     let on_beat = |info| {
-        println!("Found beat at {:?}ms", info);
+        println!("Found {}", info);
     };
     // actually start listening in thread
     let handle =
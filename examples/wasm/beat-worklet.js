@@ -0,0 +1,37 @@
+// Example glue to use beat-detector from an AudioWorklet.
+//
+// Build the crate with `wasm-pack build --target web -- --features wasm` and
+// register this file with `audioContext.audioWorklet.addModule("beat-worklet.js")`.
+// Afterwards connect an `AudioWorkletNode(audioContext, "beat-detector")` to your
+// source and listen to `node.port.onmessage` for detected beats.
+
+import init, { WasmBeatDetector } from "./pkg/beat_detector.js";
+
+class BeatDetectorProcessor extends AudioWorkletProcessor {
+    constructor() {
+        super();
+        this.detector = null;
+        // the compiled WASM module is passed in from the main thread, because
+        // worklets can't fetch it themselves
+        this.port.onmessage = async (event) => {
+            await init(event.data.wasmModule);
+            this.detector = new WasmBeatDetector(sampleRate);
+        };
+    }
+
+    process(inputs) {
+        const input = inputs[0];
+        if (this.detector === null || input.length === 0) {
+            return true;
+        }
+        // first channel only; the detector expects mono audio
+        const beat = this.detector.on_new_audio(input[0]);
+        if (beat !== null) {
+            this.port.postMessage({ relativeMs: beat.relativeMs, intensity: beat.intensity });
+            beat.free();
+        }
+        return true;
+    }
+}
+
+registerProcessor("beat-detector", BeatDetectorProcessor);
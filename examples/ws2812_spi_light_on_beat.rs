@@ -66,7 +66,7 @@ fn main() {
     let strategy = select_strategy();
     let anim_t = anim.clone();
     let on_beat = move |info| {
-        println!("Found beat at {:?}ms", info);
+        println!("Found {}", info);
         anim_t.lock().unwrap().add_next_light_impulse();
     };
     let handle =
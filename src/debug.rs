@@ -0,0 +1,127 @@
+/*
+MIT License
+
+Copyright (c) 2021 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module to visualize the audio and the detected beats for debugging.
+//! Only available with the `plot` feature.
+
+use crate::BeatInfo;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Width of the rendered image in pixels.
+const WIDTH: usize = 1200;
+/// Height of the rendered image in pixels.
+const HEIGHT: usize = 300;
+
+/// Renders the waveform of mono audio data with a marker at each beat into an
+/// SVG file. See [`render_svg_to`].
+pub fn render_svg(
+    samples: &[i16],
+    beats: &[BeatInfo],
+    sampling_rate: u32,
+    path: impl AsRef<Path>,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    render_svg_to(samples, beats, sampling_rate, &mut writer)?;
+    writer.flush()
+}
+
+/// Renders the waveform of mono audio data with beat markers as SVG image.
+///
+/// Each beat gets a vertical marker at its time. The opacity of a marker corresponds to the
+/// intensity of the beat, and hovering it shows its details. This makes bug
+/// reports and tuning easier than looking for the beats in an audio editor.
+pub fn render_svg_to<W: Write>(
+    samples: &[i16],
+    beats: &[BeatInfo],
+    sampling_rate: u32,
+    mut writer: W,
+) -> std::io::Result<()> {
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        WIDTH, HEIGHT
+    )?;
+    writeln!(writer, r#"<rect width="100%" height="100%" fill="white"/>"#)?;
+
+    // one vertical line from the minimum to the maximum per pixel column
+    let samples_per_px = (samples.len() as f32 / WIDTH as f32).max(1.0);
+    let to_y = |sample: i16| {
+        let half_height = HEIGHT as f32 / 2.0;
+        half_height - sample as f32 / i16::MAX as f32 * half_height
+    };
+    write!(writer, r#"<path stroke="steelblue" fill="none" d=""#)?;
+    for x in 0..WIDTH {
+        let begin = (x as f32 * samples_per_px) as usize;
+        let end = (((x + 1) as f32 * samples_per_px) as usize).min(samples.len());
+        if begin >= end {
+            break;
+        }
+        let column = &samples[begin..end];
+        let max = column.iter().max().copied().unwrap_or(0);
+        let min = column.iter().min().copied().unwrap_or(0);
+        write!(writer, "M{}.5 {:.1}V{:.1}", x, to_y(max), to_y(min))?;
+    }
+    writeln!(writer, r#""/>"#)?;
+
+    for beat in beats {
+        let sample = beat.relative_ms() as f32 * sampling_rate as f32 / 1000.0;
+        let x = sample / samples_per_px;
+        writeln!(
+            writer,
+            r#"<line x1="{0:.1}" y1="0" x2="{0:.1}" y2="{1}" stroke="red" stroke-width="2" stroke-opacity="{2:.2}"><title>{3}</title></line>"#,
+            x,
+            HEIGHT,
+            0.3 + 0.7 * beat.intensity(),
+            beat
+        )?;
+    }
+    writeln!(writer, "</svg>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::synth_beats;
+    use crate::StrategyKind;
+
+    #[test]
+    fn test_render_svg_to() {
+        let samples = synth_beats(44100, 120.0, 4, 50.0);
+        let detector = StrategyKind::LPF.detector(44100);
+        let beats = samples
+            .chunks(1024)
+            .filter_map(|window| detector.is_beat(window))
+            .collect::<Vec<_>>();
+
+        let mut out = Vec::new();
+        render_svg_to(&samples, &beats, 44100, &mut out).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(4, svg.matches("<line ").count());
+        // one column per pixel
+        assert_eq!(WIDTH, svg.matches('V').count());
+    }
+}
@@ -0,0 +1,239 @@
+/*
+MIT License
+
+Copyright (c) 2021 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module to export detected beats into file formats of other tools.
+
+use crate::BeatInfo;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Resolution of the MIDI file in ticks per quarter note.
+const MIDI_TICKS_PER_QUARTER: u16 = 480;
+
+/// Writes the beats as markers into a Standard MIDI File, e.g. to import them
+/// into a DAW. See [`write_midi_markers_to`].
+pub fn write_midi_markers(
+    beats: &[BeatInfo],
+    bpm: f32,
+    path: impl AsRef<Path>,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_midi_markers_to(beats, bpm, &mut writer)?;
+    writer.flush()
+}
+
+/// Writes the beats as markers into a Standard MIDI File (format 0).
+///
+/// The file has a tempo event for `bpm` and a marker named `Beat <n>` at the
+/// timestamp of each beat. The tempo is used to
+/// convert the timestamps into MIDI ticks, so the markers are at the right
+/// position in time regardless of the tempo. Use the tempo of the music, e.g.
+/// from [`crate::tempo::TempoTracker::bpm`], to make the grid of the DAW match.
+/// The beats must be ordered by time.
+pub fn write_midi_markers_to<W: Write>(
+    beats: &[BeatInfo],
+    bpm: f32,
+    mut writer: W,
+) -> std::io::Result<()> {
+    assert!(bpm > 0.0, "The tempo must be greater than 0!");
+    let mut track = Vec::new();
+
+    // tempo in microseconds per quarter note (24 bit)
+    let us_per_quarter = ((60_000_000.0 / bpm) as u32).min(0xFF_FF_FF);
+    write_variable_length(&mut track, 0);
+    track.extend_from_slice(&[0xFF, 0x51, 0x03]);
+    track.extend_from_slice(&us_per_quarter.to_be_bytes()[1..]);
+
+    let mut last_tick = 0;
+    for (i, beat) in beats.iter().enumerate() {
+        let tick = (beat.relative_ms() as f64 * bpm as f64 * MIDI_TICKS_PER_QUARTER as f64
+            / 60_000.0)
+            .round() as u32;
+        let name = format!("Beat {}", i + 1);
+        write_variable_length(&mut track, tick.saturating_sub(last_tick));
+        track.extend_from_slice(&[0xFF, 0x06]);
+        write_variable_length(&mut track, name.len() as u32);
+        track.extend_from_slice(name.as_bytes());
+        last_tick = tick.max(last_tick);
+    }
+
+    // end of track
+    write_variable_length(&mut track, 0);
+    track.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+
+    writer.write_all(b"MThd")?;
+    writer.write_all(&6_u32.to_be_bytes())?;
+    // format 0, one track
+    writer.write_all(&0_u16.to_be_bytes())?;
+    writer.write_all(&1_u16.to_be_bytes())?;
+    writer.write_all(&MIDI_TICKS_PER_QUARTER.to_be_bytes())?;
+    writer.write_all(b"MTrk")?;
+    writer.write_all(&(track.len() as u32).to_be_bytes())?;
+    writer.write_all(&track)
+}
+
+/// Writes the beats as CSV table with a header, e.g. for analysis in a
+/// spreadsheet or with pandas.
+///
+/// The columns are `time_ms`, `intensity` and `brightness` (see [`BeatInfo`]).
+/// Floats are written with three decimals so that the output is deterministic
+/// and diffs stay stable.
+pub fn write_csv<W: Write>(beats: &[BeatInfo], mut writer: W) -> std::io::Result<()> {
+    writeln!(writer, "time_ms,intensity,brightness")?;
+    for beat in beats {
+        writeln!(
+            writer,
+            "{},{:.3},{:.3}",
+            beat.relative_ms(),
+            beat.intensity(),
+            beat.brightness()
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes a beat as one line of JSON (JSON Lines), e.g. to pipe live beats into
+/// `jq` or a logging pipeline. See [`crate::record::start_listening_jsonl`].
+///
+/// The object has the fields `time_ms` (relative, see [`BeatInfo::relative_ms`]),
+/// `unix_ms` (absolute wall clock time in ms since the Unix epoch),
+/// `sample_index`, `intensity`, `brightness` and `bpm`, where `unix_ms` and
+/// `bpm` are `null` if they are unknown. The writer is flushed after the line,
+/// so that consumers get each beat immediately.
+pub fn write_json_line<W: Write>(
+    beat: &BeatInfo,
+    unix_ms: Option<u64>,
+    bpm: Option<f32>,
+    mut writer: W,
+) -> std::io::Result<()> {
+    let unix_ms = unix_ms.map_or_else(|| String::from("null"), |ms| ms.to_string());
+    let bpm = match bpm {
+        Some(bpm) if bpm.is_finite() => format!("{:.3}", bpm),
+        _ => String::from("null"),
+    };
+    writeln!(
+        writer,
+        r#"{{"time_ms":{},"unix_ms":{},"sample_index":{},"intensity":{:.3},"brightness":{:.3},"bpm":{}}}"#,
+        beat.relative_ms(),
+        unix_ms,
+        beat.sample_index(),
+        beat.intensity(),
+        beat.brightness(),
+        bpm
+    )?;
+    writer.flush()
+}
+
+/// Appends a MIDI variable-length quantity: 7 bits per byte, most significant
+/// group first, the highest bit marks that another byte follows.
+fn write_variable_length(out: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_variable_length() {
+        let encode = |value| {
+            let mut out = Vec::new();
+            write_variable_length(&mut out, value);
+            out
+        };
+        assert_eq!(vec![0x00], encode(0));
+        assert_eq!(vec![0x7F], encode(0x7F));
+        assert_eq!(vec![0x81, 0x00], encode(0x80));
+        assert_eq!(vec![0xC0, 0x00], encode(0x2000));
+        assert_eq!(vec![0xFF, 0xFF, 0x7F], encode(0x1F_FF_FF));
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let beats = [
+            BeatInfo::new(500, 1.0),
+            BeatInfo::new(1023, 0.45678).with_brightness(0.0125),
+        ];
+        let mut out = Vec::new();
+        write_csv(&beats, &mut out).unwrap();
+        assert_eq!(
+            "time_ms,intensity,brightness\n500,1.000,0.000\n1023,0.457,0.013\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_json_line() {
+        let mut tracker = crate::tempo::TempoTracker::new();
+        let mut out = Vec::new();
+        for n in 1..=3 {
+            let beat = BeatInfo::new(n * 500, 0.5)
+                .with_brightness(0.25)
+                .with_sample_index(n as usize * 22050);
+            tracker.on_beat(&beat);
+            let unix_ms = Some(1_600_000_000_000 + n as u64 * 500).filter(|_| n > 1);
+            write_json_line(&beat, unix_ms, tracker.bpm(), &mut out).unwrap();
+        }
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                r#"{"time_ms":500,"unix_ms":null,"sample_index":22050,"intensity":0.500,"brightness":0.250,"bpm":null}"#,
+                r#"{"time_ms":1000,"unix_ms":1600000001000,"sample_index":44100,"intensity":0.500,"brightness":0.250,"bpm":120.000}"#,
+                r#"{"time_ms":1500,"unix_ms":1600000001500,"sample_index":66150,"intensity":0.500,"brightness":0.250,"bpm":120.000}"#,
+            ],
+            lines
+        );
+        assert!(out.ends_with('\n'));
+    }
+
+    #[test]
+    fn test_write_midi_markers() {
+        let beats = [BeatInfo::new(500, 1.0), BeatInfo::new(1000, 1.0)];
+        let mut out = Vec::new();
+        write_midi_markers_to(&beats, 120.0, &mut out).unwrap();
+
+        assert_eq!(b"MThd", &out[0..4]);
+        assert_eq!(&[0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xE0], &out[4..14]);
+        assert_eq!(b"MTrk", &out[14..18]);
+        let track_len = u32::from_be_bytes([out[18], out[19], out[20], out[21]]) as usize;
+        let track = &out[22..];
+        assert_eq!(track_len, track.len());
+
+        // 120 BPM => 500000us per quarter
+        assert_eq!(&[0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20], &track[0..7]);
+        // 500ms at 120 BPM is one quarter note, i.e. 480 ticks
+        assert_eq!(&[0x83, 0x60, 0xFF, 0x06, 6], &track[7..12]);
+        assert_eq!(b"Beat 1", &track[12..18]);
+        assert_eq!(&[0x83, 0x60, 0xFF, 0x06, 6], &track[18..23]);
+        assert_eq!(b"Beat 2", &track[23..29]);
+        assert_eq!(&[0x00, 0xFF, 0x2F, 0x00], &track[29..]);
+    }
+}
@@ -0,0 +1,1040 @@
+/*
+MIT License
+
+Copyright (c) 2021 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for beat detection on audio data from arbitrary sources,
+//! e.g. files or network streams, without loading everything into memory.
+
+#[cfg(feature = "wav")]
+use crate::record::{f32_sample_to_i16, u8_sample_to_f32_sample};
+use crate::strategies::window_stats::WindowStats;
+use crate::{AnalysisOutcome, BeatInfo, Strategy, StrategyKind};
+use std::cell::{Cell, UnsafeCell};
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read};
+#[cfg(feature = "wav")]
+use std::io::{Seek, SeekFrom, Take, Write};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Amount of samples that are read and analyzed at once.
+/// 1/44100 * 1024 = 23.22ms
+const BLOCK_LEN: usize = 1024;
+
+/// Streams mono audio data from a [`Read`] source in blocks of fixed size through
+/// a [`Strategy`].
+///
+/// The data must be signed 16 bit samples in little endian byte
+/// order, i.e. raw PCM as in most WAV files. The memory usage is bounded by the
+/// block size, independent of the length of the recording.
+///
+/// Implements [`Iterator`] over all beats found in the stream. The iterator ends
+/// at the end of the stream or at the first I/O error, see
+/// [`StreamAnalyzer::take_error`].
+#[derive(Debug)]
+pub struct StreamAnalyzer<R: Read> {
+    reader: R,
+    encoding: SampleEncoding,
+    detector: Box<dyn Strategy + Send>,
+    byte_buf: Vec<u8>,
+    sample_buf: Vec<i16>,
+    /// Lengths of the next blocks; [`BLOCK_LEN`] is used when it's empty.
+    block_lens: VecDeque<usize>,
+    eof: bool,
+    error: Option<std::io::Error>,
+}
+
+impl<R: Read> StreamAnalyzer<R> {
+    /// Constructor for [`StreamAnalyzer`].
+    pub fn new(reader: R, strategy: StrategyKind, sampling_rate: u32) -> Self {
+        Self::with_encoding(reader, SampleEncoding::I16, strategy, sampling_rate)
+    }
+
+    fn with_encoding(
+        reader: R,
+        encoding: SampleEncoding,
+        strategy: StrategyKind,
+        sampling_rate: u32,
+    ) -> Self {
+        Self {
+            reader,
+            encoding,
+            detector: strategy.detector(sampling_rate),
+            byte_buf: vec![0; BLOCK_LEN * encoding.bytes_per_sample()],
+            sample_buf: Vec::with_capacity(BLOCK_LEN),
+            block_lens: VecDeque::new(),
+            eof: false,
+            error: None,
+        }
+    }
+
+    /// Analyzes the first blocks with the given lengths in samples instead of
+    /// [`BLOCK_LEN`], e.g. to reproduce the chunks of a live recording. Lengths
+    /// of `0` are ignored.
+    #[cfg(feature = "wav")]
+    pub(crate) fn with_block_lens(mut self, block_lens: Vec<usize>) -> Self {
+        self.block_lens = block_lens.into_iter().filter(|len| *len > 0).collect();
+        self
+    }
+
+    /// Returns the I/O error that ended the iteration, if there was one.
+    pub fn take_error(&mut self) -> Option<std::io::Error> {
+        self.error.take()
+    }
+
+    /// Reads the next block of samples into `sample_buf`. The last block of a
+    /// stream may be shorter than requested; a trailing incomplete sample is
+    /// ignored.
+    fn read_block(&mut self) -> bool {
+        let block_len = self.block_lens.pop_front().unwrap_or(BLOCK_LEN);
+        self.byte_buf
+            .resize(block_len * self.encoding.bytes_per_sample(), 0);
+        let mut len = 0;
+        while len < self.byte_buf.len() {
+            match self.reader.read(&mut self.byte_buf[len..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(n) => len += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.error = Some(e);
+                    self.eof = true;
+                    break;
+                }
+            }
+        }
+
+        self.sample_buf.clear();
+        let bytes = self.byte_buf[..len].chunks_exact(self.encoding.bytes_per_sample());
+        match self.encoding {
+            SampleEncoding::I16 => self
+                .sample_buf
+                .extend(bytes.map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))),
+            #[cfg(feature = "wav")]
+            SampleEncoding::U8 => self
+                .sample_buf
+                .extend(bytes.map(|bytes| f32_sample_to_i16(u8_sample_to_f32_sample(bytes[0])))),
+        }
+        !self.sample_buf.is_empty()
+    }
+}
+
+#[cfg(feature = "wav")]
+impl<R: Read> StreamAnalyzer<Take<R>> {
+    /// Creates a [`StreamAnalyzer`] for the audio data of a WAV file, e.g. one
+    /// that was written by [`WavWriter`]. Only mono PCM with signed 16 bit or
+    /// unsigned 8 bit samples is supported.
+    pub fn from_wav(mut reader: R, strategy: StrategyKind) -> Result<Self, String> {
+        let (sampling_rate, encoding, data_len) = read_wav_header(&mut reader)?;
+        Ok(Self::with_encoding(
+            reader.take(data_len as u64),
+            encoding,
+            strategy,
+            sampling_rate,
+        ))
+    }
+}
+
+/// Encoding of the samples that a [`StreamAnalyzer`] reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SampleEncoding {
+    /// Signed 16 bit in little endian byte order.
+    I16,
+    /// Unsigned 8 bit, as used by 8 bit WAV files.
+    #[cfg(feature = "wav")]
+    U8,
+}
+
+impl SampleEncoding {
+    #[inline(always)]
+    const fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::I16 => 2,
+            #[cfg(feature = "wav")]
+            Self::U8 => 1,
+        }
+    }
+}
+
+impl<R: Read> Iterator for StreamAnalyzer<R> {
+    type Item = BeatInfo;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.eof {
+            if !self.read_block() {
+                break;
+            }
+            if let Some(info) = self.detector.is_beat(&self.sample_buf) {
+                return Some(info);
+            }
+        }
+        None
+    }
+}
+
+/// Source of mono audio data for [`listen`].
+///
+/// Implement it to use audio backends other than `cpal`, e.g. JACK, a ring buffer that is filled by another thread,
+/// or synthetic audio in tests. See [`crate::record::CpalSource`] for a
+/// `cpal`-backed implementation.
+pub trait AudioSource {
+    /// Returns the next block of mono audio data. May block until data is
+    /// available. `None` means the source is exhausted.
+    fn next_block(&mut self) -> Option<&[i16]>;
+
+    /// Sampling rate of the audio data in Hz.
+    fn sampling_rate(&self) -> u32;
+}
+
+/// Pumps all blocks of `source` through a detector of the given strategy and
+/// calls `on_beat` for each beat. Returns when the source is exhausted.
+pub fn listen<S: AudioSource>(
+    mut source: S,
+    strategy: StrategyKind,
+    mut on_beat: impl FnMut(BeatInfo),
+) {
+    let detector = strategy.detector(source.sampling_rate());
+    while let Some(block) = source.next_block() {
+        if let Some(info) = detector.is_beat(block) {
+            on_beat(info);
+        }
+    }
+}
+
+/// Runs a [`Strategy`] on windows of a fixed hop size, independent of the size
+/// of the chunks the audio arrives in.
+///
+/// Audio callbacks deliver chunks of varying sizes, so the analysis windows and
+/// therefore the time resolution of the beats depend on the audio driver. This
+/// analyzer buffers incoming samples and runs the analysis each time `hop_size`
+/// new samples are available; the remainder is kept for the next call.
+///
+/// A smaller hop size gives a finer time resolution and a lower detection
+/// latency (at most one hop), but the analysis runs more often, which costs more
+/// CPU. Very small hop sizes also make the windows too short to contain a full
+/// period of low frequencies. 1024 samples (23ms at 44.1kHz) is a good default.
+#[derive(Debug)]
+pub struct HopAnalyzer {
+    detector: Box<dyn Strategy + Send>,
+    hop_size: usize,
+    pending: Vec<i16>,
+}
+
+impl HopAnalyzer {
+    /// Constructor for [`HopAnalyzer`]. Panics if `hop_size` is `0`.
+    pub fn new(strategy: StrategyKind, sampling_rate: u32, hop_size: usize) -> Self {
+        assert!(hop_size > 0, "The hop size must be greater than 0!");
+        Self {
+            detector: strategy.detector(sampling_rate),
+            hop_size,
+            pending: Vec::with_capacity(hop_size),
+        }
+    }
+
+    /// Getter for the hop size in samples.
+    #[inline(always)]
+    pub const fn hop_size(&self) -> usize {
+        self.hop_size
+    }
+
+    /// Amount of samples that are buffered until the next hop is complete.
+    #[inline(always)]
+    pub fn pending_samples(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Getter for the underlying detector, e.g. to query its timing.
+    #[inline(always)]
+    pub fn detector(&self) -> &dyn Strategy {
+        self.detector.as_ref()
+    }
+
+    /// Adds new mono samples and analyzes all hops that are complete afterwards.
+    /// Calls `on_beat` for each beat found.
+    pub fn process(&mut self, samples: &[i16], mut on_beat: impl FnMut(BeatInfo)) {
+        self.process_windows(samples, |_detector, _window, beat| {
+            if let Some(info) = beat {
+                on_beat(info);
+            }
+        });
+    }
+
+    /// Like [`HopAnalyzer::process`] but calls `on_window` for each analyzed hop
+    /// with the detector, the samples of the hop and the result.
+    fn process_windows(
+        &mut self,
+        mut samples: &[i16],
+        mut on_window: impl FnMut(&dyn Strategy, &[i16], Option<BeatInfo>),
+    ) {
+        while !samples.is_empty() {
+            let missing = self.hop_size - self.pending.len();
+            let (head, tail) = samples.split_at(missing.min(samples.len()));
+            samples = tail;
+            // analyze directly from the input if nothing is buffered
+            let window = if self.pending.is_empty() && head.len() == self.hop_size {
+                head
+            } else {
+                self.pending.extend_from_slice(head);
+                if self.pending.len() < self.hop_size {
+                    break;
+                }
+                &self.pending
+            };
+            let beat = self.detector.is_beat(window);
+            on_window(self.detector.as_ref(), window, beat);
+            self.pending.clear();
+        }
+    }
+}
+
+/// Features of one analyzed window, see [`FixedWindowAnalyzer`].
+#[derive(Debug, Clone, Copy)]
+pub struct FeatureFrame {
+    /// Time of the middle of the window in ms. It is on the same timeline as
+    /// [`BeatInfo::relative_ms`], i.e. it respects [`Strategy::set_time_origin`].
+    pub time_ms: u32,
+    /// Index of the sample in the middle of the window since the beginning of the
+    /// stream, like [`BeatInfo::sample_index`].
+    pub sample_index: usize,
+    /// Mean square of the samples in range `[0, 1]`.
+    pub energy: f32,
+    /// Maximum amplitude in range `[0, 1]`, like [`BeatInfo::intensity`].
+    pub intensity: f32,
+    /// Approximated spectral centroid in range `[0, 1]`, like [`BeatInfo::brightness`].
+    pub brightness: f32,
+    /// See [`Strategy::beat_activation`].
+    pub activation: f32,
+    /// Why the window was or wasn't a beat, see [`Strategy::last_analysis`].
+    pub outcome: AnalysisOutcome,
+    /// Whether the detector found a beat in the window.
+    pub beat: bool,
+}
+
+/// Like [`HopAnalyzer`] but emits a [`FeatureFrame`] for every hop instead of only
+/// the beats, e.g. as regular input for ML pipelines.
+///
+/// The frames expose the signals that the detector computes anyway. Each hop of
+/// the input results in exactly one frame, independent of how the input is
+/// chunked.
+#[derive(Debug)]
+pub struct FixedWindowAnalyzer {
+    hop_analyzer: HopAnalyzer,
+}
+
+impl FixedWindowAnalyzer {
+    /// Constructor for [`FixedWindowAnalyzer`]. Panics if `hop_size` is `0`.
+    pub fn new(strategy: StrategyKind, sampling_rate: u32, hop_size: usize) -> Self {
+        Self {
+            hop_analyzer: HopAnalyzer::new(strategy, sampling_rate, hop_size),
+        }
+    }
+
+    /// Getter for the hop size in samples.
+    #[inline(always)]
+    pub const fn hop_size(&self) -> usize {
+        self.hop_analyzer.hop_size()
+    }
+
+    /// Getter for the underlying detector, e.g. to query its timing.
+    #[inline(always)]
+    pub fn detector(&self) -> &dyn Strategy {
+        self.hop_analyzer.detector()
+    }
+
+    /// Adds new mono samples and calls `on_frame` for each hop that is complete
+    /// afterwards.
+    pub fn process(&mut self, samples: &[i16], mut on_frame: impl FnMut(FeatureFrame)) {
+        self.hop_analyzer
+            .process_windows(samples, |detector, window, beat| {
+                let stats = WindowStats::from(window);
+                let energy = window
+                    .iter()
+                    .map(|x| (*x as f32 / i16::MAX as f32).powi(2))
+                    .sum::<f32>()
+                    / window.len() as f32;
+                on_frame(FeatureFrame {
+                    time_ms: detector.state().reported_beat_time_ms(),
+                    sample_index: detector.state().beat_sample_index(),
+                    energy: energy.min(1.0),
+                    intensity: stats.intensity(),
+                    brightness: stats.brightness(),
+                    activation: detector.beat_activation(),
+                    outcome: detector.last_analysis().outcome,
+                    beat: beat.is_some(),
+                })
+            });
+    }
+
+    /// Like [`FixedWindowAnalyzer::process`] but collects the frames.
+    pub fn frames(&mut self, samples: &[i16]) -> Vec<FeatureFrame> {
+        let mut frames = Vec::new();
+        self.process(samples, |frame| frames.push(frame));
+        frames
+    }
+}
+
+/// Detects all beats in mono audio data that is completely in memory, e.g. a
+/// decoded file.
+///
+/// This is a shortcut for the streaming path, e.g. [`StreamAnalyzer`]: a fresh
+/// detector analyzes the data in consecutive windows of 1024 samples, and each
+/// window is filtered separately like in live mode. The signal is not filtered
+/// as a whole, i.e. effects at the window boundaries remain. The result doesn't
+/// depend on how the data was chunked when it was recorded, so it is repeatable
+/// for the same data. A trailing window shorter than 1024 samples is analyzed,
+/// too.
+pub fn analyze_offline(
+    samples: &[i16],
+    sampling_rate: u32,
+    strategy: StrategyKind,
+) -> Vec<BeatInfo> {
+    let detector = strategy.detector(sampling_rate);
+    samples
+        .chunks(BLOCK_LEN)
+        .filter_map(|window| detector.is_beat(window))
+        .collect()
+}
+
+/// Creates a bounded single-producer single-consumer queue for beats that can
+/// hold up to `capacity` beats. Panics if `capacity` is `0`.
+///
+/// Pushing and popping never lock or allocate, i.e. the producer can be used
+/// inside a real-time audio callback while the consumer drains the beats on its
+/// own thread. Blocking in an audio callback, e.g. on a mutex that the GUI thread
+/// holds, delays the audio driver and causes dropouts and glitches.
+pub fn beat_queue(capacity: usize) -> (BeatProducer, BeatConsumer) {
+    assert!(capacity > 0, "The capacity must be greater than 0!");
+    let queue = Arc::new(BeatQueue {
+        // one slot stays empty to distinguish a full from an empty queue
+        slots: (0..capacity + 1)
+            .map(|_| UnsafeCell::new(BeatInfo::new(0, 0.0)))
+            .collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (
+        BeatProducer {
+            queue: queue.clone(),
+            _not_sync: PhantomData,
+        },
+        BeatConsumer {
+            queue,
+            _not_sync: PhantomData,
+        },
+    )
+}
+
+/// Ring buffer shared between a [`BeatProducer`] and a [`BeatConsumer`].
+#[derive(Debug)]
+struct BeatQueue {
+    slots: Box<[UnsafeCell<BeatInfo>]>,
+    /// Index of the next slot to read. Only written by the consumer.
+    head: AtomicUsize,
+    /// Index of the next slot to write. Only written by the producer.
+    tail: AtomicUsize,
+}
+
+// Safety: there is exactly one producer and one consumer, because both are
+// neither `Clone` nor `Sync`, i.e. each is only used by one thread at a time.
+// A slot is only accessed by one of them at a time, which is synchronized via
+// `head` and `tail`.
+unsafe impl Sync for BeatQueue {}
+
+impl BeatQueue {
+    #[inline(always)]
+    fn next_index(&self, index: usize) -> usize {
+        (index + 1) % self.slots.len()
+    }
+}
+
+/// Sending half of a [`beat_queue`].
+///
+/// It can be moved to another thread but not shared between threads, because
+/// concurrent pushes would race:
+///
+/// ```compile_fail,E0277
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<beat_detector::io::BeatProducer>();
+/// ```
+#[derive(Debug)]
+pub struct BeatProducer {
+    queue: Arc<BeatQueue>,
+    /// Makes the type `!Sync`.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl BeatProducer {
+    /// Adds a beat to the queue. Returns the beat as error if the queue is full,
+    /// i.e. the consumer doesn't keep up.
+    pub fn push(&self, info: BeatInfo) -> Result<(), BeatInfo> {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        let next = self.queue.next_index(tail);
+        if next == self.queue.head.load(Ordering::Acquire) {
+            return Err(info);
+        }
+        // Safety: the consumer doesn't read the slot until `tail` is published
+        unsafe { *self.queue.slots[tail].get() = info };
+        self.queue.tail.store(next, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Receiving half of a [`beat_queue`].
+///
+/// It can be moved to another thread but not shared between threads, because
+/// concurrent pops would race:
+///
+/// ```compile_fail,E0277
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<beat_detector::io::BeatConsumer>();
+/// ```
+#[derive(Debug)]
+pub struct BeatConsumer {
+    queue: Arc<BeatQueue>,
+    /// Makes the type `!Sync`.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl BeatConsumer {
+    /// Takes the oldest beat from the queue, if there is any. Never blocks.
+    pub fn pop(&self) -> Option<BeatInfo> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        if head == self.queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        // Safety: the producer doesn't write the slot until `head` is published
+        let info = unsafe { *self.queue.slots[head].get() };
+        self.queue
+            .head
+            .store(self.queue.next_index(head), Ordering::Release);
+        Some(info)
+    }
+
+    /// Iterator that takes all beats that are currently in the queue.
+    pub fn drain(&self) -> impl Iterator<Item = BeatInfo> + '_ {
+        std::iter::from_fn(move || self.pop())
+    }
+}
+
+/// Scales audio data in place so that its peak magnitude is exactly `1.0`, i.e.
+/// the data is in range `[-1, 1]` as expected by [`Strategy::is_beat_f32`].
+///
+/// Useful for data in arbitrary float ranges, e.g. after an FFT or mixing that
+/// peaks above `1.0`. Silence (all zeros) is left unchanged. Note that this also
+/// amplifies quiet audio, which affects the amplitude checks of the detector.
+pub fn normalize_into_unit_range(samples: &mut [f32]) {
+    let peak = samples
+        .iter()
+        .fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+    if peak > 0.0 {
+        samples.iter_mut().for_each(|sample| *sample /= peak);
+    }
+}
+
+/// Like [`normalize_into_unit_range`] but returns a normalized copy.
+pub fn normalized(samples: &[f32]) -> Vec<f32> {
+    let mut samples = samples.to_vec();
+    normalize_into_unit_range(&mut samples);
+    samples
+}
+
+/// Minimal writer for mono 16 bit PCM WAV files, e.g. to capture the exact audio
+/// a detector saw for a bug report. The header is completed when the writer is
+/// dropped or [`WavWriter::finish`] is called.
+#[cfg(feature = "wav")]
+#[derive(Debug)]
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    /// Length of the audio data in bytes.
+    data_len: u32,
+}
+
+#[cfg(feature = "wav")]
+impl<W: Write + Seek> WavWriter<W> {
+    /// Size of the RIFF header including the "fmt " and "data" chunk headers.
+    const HEADER_LEN: u32 = 44;
+    /// Maximum length of the audio data in bytes, so that the length of the RIFF
+    /// chunk still fits into its 32 bit field (4 GiB).
+    const MAX_DATA_LEN: u32 = u32::MAX - (Self::HEADER_LEN - 8);
+
+    /// Constructor for [`WavWriter`]. Writes the header immediately.
+    pub fn new(mut writer: W, sampling_rate: u32) -> std::io::Result<Self> {
+        let byte_rate = sampling_rate.checked_mul(2).ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidInput, "Sampling rate is too high for WAV")
+        })?;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(Self::HEADER_LEN - 8).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16_u32.to_le_bytes())?;
+        // PCM, mono
+        writer.write_all(&1_u16.to_le_bytes())?;
+        writer.write_all(&1_u16.to_le_bytes())?;
+        writer.write_all(&sampling_rate.to_le_bytes())?;
+        // byte rate, block align, bits per sample
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&2_u16.to_le_bytes())?;
+        writer.write_all(&16_u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0_u32.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            data_len: 0,
+        })
+    }
+
+    /// Appends mono samples. Fails without writing anything if the audio data
+    /// would exceed the 4 GiB limit of the RIFF format.
+    pub fn write_samples(&mut self, samples: &[i16]) -> std::io::Result<()> {
+        let data_len = (samples.len() as u64)
+            .saturating_mul(2)
+            .checked_add(self.data_len as u64)
+            .filter(|len| *len <= Self::MAX_DATA_LEN as u64)
+            .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "WAV file exceeds 4 GiB"))?;
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len = data_len as u32;
+        Ok(())
+    }
+
+    /// Updates the length fields in the header so that the file is valid.
+    /// Can be called multiple times, e.g. periodically during a long recording.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(Self::HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.writer
+            .seek(SeekFrom::Start(Self::HEADER_LEN as u64 - 4))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(feature = "wav")]
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("Can't finish WAV file: {:?}", err);
+        }
+    }
+}
+
+/// Reads the header of a WAV file until the beginning of the audio data.
+/// Returns the sampling rate, the encoding of the samples and the length of the
+/// audio data in bytes.
+#[cfg(feature = "wav")]
+fn read_wav_header<R: Read>(reader: &mut R) -> Result<(u32, SampleEncoding, u32), String> {
+    let mut riff = [0; 12];
+    reader
+        .read_exact(&mut riff)
+        .map_err(|err| format!("Can't read WAV header: {:?}", err))?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err("Not a WAV file!".to_string());
+    }
+
+    let mut stream_format = None;
+    loop {
+        let mut chunk_header = [0; 8];
+        reader
+            .read_exact(&mut chunk_header)
+            .map_err(|err| format!("Can't read WAV chunk: {:?}", err))?;
+        let chunk_len = u32::from_le_bytes([
+            chunk_header[4],
+            chunk_header[5],
+            chunk_header[6],
+            chunk_header[7],
+        ]);
+        match &chunk_header[0..4] {
+            b"fmt " => {
+                // the size comes from the file, so only the fixed part is read
+                if chunk_len < 16 {
+                    return Err("Invalid WAV format chunk!".to_string());
+                }
+                let mut fmt = [0; 16];
+                reader
+                    .read_exact(&mut fmt)
+                    .map_err(|err| format!("Can't read WAV format: {:?}", err))?;
+                // skip extensions of the format, e.g. of WAVE_FORMAT_EXTENSIBLE
+                skip_wav_chunk(reader, chunk_len - 16, chunk_len)?;
+                let format = u16::from_le_bytes([fmt[0], fmt[1]]);
+                let channels = u16::from_le_bytes([fmt[2], fmt[3]]);
+                let bits_per_sample = u16::from_le_bytes([fmt[14], fmt[15]]);
+                // 8 bit WAV samples are unsigned, 16 bit WAV samples are signed
+                let encoding = match (format, channels, bits_per_sample) {
+                    (1, 1, 16) => SampleEncoding::I16,
+                    (1, 1, 8) => SampleEncoding::U8,
+                    _ => {
+                        return Err(format!(
+                            "Only mono 8/16 bit PCM is supported; format={}, channels={}, bits={}",
+                            format, channels, bits_per_sample
+                        ))
+                    }
+                };
+                let sampling_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+                stream_format = Some((sampling_rate, encoding));
+            }
+            b"data" => {
+                let (sampling_rate, encoding) =
+                    stream_format.ok_or_else(|| "WAV data before format chunk!".to_string())?;
+                return Ok((sampling_rate, encoding, chunk_len));
+            }
+            // skip unknown chunks, e.g. "LIST"
+            _ => skip_wav_chunk(reader, chunk_len, chunk_len)?,
+        }
+    }
+}
+
+/// Skips `len` bytes of a chunk with the total length `chunk_len`, including the
+/// padding byte of chunks with an odd length.
+#[cfg(feature = "wav")]
+fn skip_wav_chunk<R: Read>(reader: &mut R, len: u32, chunk_len: u32) -> Result<(), String> {
+    let padded_len = len as u64 + chunk_len as u64 % 2;
+    std::io::copy(&mut reader.take(padded_len), &mut std::io::sink())
+        .map_err(|err| format!("Can't read WAV chunk: {:?}", err))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::synth_beats;
+    use std::io::Cursor;
+
+    /// Creates raw PCM bytes with a loud 50Hz sine burst at the given sample offset.
+    fn pcm_with_burst(len: usize, burst_begin: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| {
+                if i >= burst_begin && i < burst_begin + 2000 {
+                    let t = (i - burst_begin) as f32 / 44100.0;
+                    (0.9 * (2.0 * std::f32::consts::PI * 50.0 * t).sin() * i16::MAX as f32) as i16
+                } else {
+                    0
+                }
+            })
+            .flat_map(|sample| sample.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_stream_analyzer_finds_beat() {
+        let pcm = pcm_with_burst(44100, 22050);
+        let beats =
+            StreamAnalyzer::new(Cursor::new(pcm), StrategyKind::LPF, 44100).collect::<Vec<_>>();
+        assert_eq!(1, beats.len());
+        assert!((480..=560).contains(&beats[0].relative_ms()));
+    }
+
+    #[test]
+    fn test_stream_analyzer_processes_partial_block_at_eof() {
+        // the burst starts in the last, incomplete block
+        let pcm = pcm_with_burst(30 * BLOCK_LEN + 1000, 30 * BLOCK_LEN);
+        let mut analyzer = StreamAnalyzer::new(Cursor::new(pcm), StrategyKind::LPF, 44100);
+        assert!(analyzer.next().is_some());
+        assert!(analyzer.next().is_none());
+        assert!(analyzer.take_error().is_none());
+    }
+
+    #[test]
+    fn test_normalize_into_unit_range() {
+        let samples = [0.5, -2.5, 1.25, 0.0];
+        let normalized = normalized(&samples);
+        assert_eq!(vec![0.2, -1.0, 0.5, 0.0], normalized);
+        let peak = normalized.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        assert_eq!(1.0, peak);
+
+        let mut silence = [0.0; 4];
+        normalize_into_unit_range(&mut silence);
+        assert_eq!([0.0; 4], silence);
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn test_wav_write_and_replay() {
+        let samples = crate::testing::synth_beats(44100, 120.0, 4, 50.0);
+        let mut wav = Cursor::new(Vec::new());
+        {
+            let mut writer = WavWriter::new(&mut wav, 44100).unwrap();
+            // like in live mode, the audio arrives in chunks of varying size
+            samples
+                .chunks(513)
+                .for_each(|chunk| writer.write_samples(chunk).unwrap());
+        }
+        let wav = wav.into_inner();
+        assert_eq!(44 + samples.len() * 2, wav.len());
+
+        let beats = StreamAnalyzer::from_wav(Cursor::new(wav), StrategyKind::LPF)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(4, beats.len());
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn test_wav_rejects_stereo() {
+        let mut wav = Cursor::new(Vec::new());
+        WavWriter::new(&mut wav, 44100).unwrap();
+        let mut wav = wav.into_inner();
+        // patch channel count
+        wav[22] = 2;
+        assert!(StreamAnalyzer::from_wav(Cursor::new(wav), StrategyKind::LPF).is_err());
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn test_wav_writer_limits() {
+        assert!(WavWriter::new(Cursor::new(Vec::new()), u32::MAX).is_err());
+
+        let mut wav = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut wav, 44100).unwrap();
+        writer.data_len = WavWriter::<Cursor<Vec<u8>>>::MAX_DATA_LEN - 2;
+        writer.write_samples(&[1]).unwrap();
+        assert!(writer.write_samples(&[2]).is_err());
+        assert_eq!(WavWriter::<Cursor<Vec<u8>>>::MAX_DATA_LEN, writer.data_len);
+        // the rejected sample wasn't written
+        drop(writer);
+        assert_eq!(46, wav.into_inner().len());
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn test_wav_format_chunk_len() {
+        let mut wav = Cursor::new(Vec::new());
+        WavWriter::new(&mut wav, 44100)
+            .unwrap()
+            .write_samples(&[0; 100])
+            .unwrap();
+        let wav = wav.into_inner();
+
+        // a format chunk with an odd length is followed by a padding byte
+        let mut odd = wav.clone();
+        odd[16..20].copy_from_slice(&17_u32.to_le_bytes());
+        odd.splice(36..36, vec![0, 0]);
+        let analyzer = StreamAnalyzer::from_wav(Cursor::new(odd), StrategyKind::LPF).unwrap();
+        assert_eq!(200, analyzer.reader.limit());
+
+        // a corrupt length must not allocate the claimed size
+        let mut corrupt = wav;
+        corrupt[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(StreamAnalyzer::from_wav(Cursor::new(corrupt), StrategyKind::LPF).is_err());
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn test_wav_unsigned_8_bit() {
+        let samples = synth_beats(44100, 120.0, 4, 50.0);
+        let mut wav = Cursor::new(Vec::new());
+        WavWriter::new(&mut wav, 44100).unwrap();
+        let mut wav = wav.into_inner();
+        // patch byte rate, block align and bits per sample
+        wav[28..32].copy_from_slice(&44100_u32.to_le_bytes());
+        wav[32] = 1;
+        wav[34] = 8;
+        wav[40..44].copy_from_slice(&(samples.len() as u32).to_le_bytes());
+        wav.extend(samples.iter().map(|x| ((*x >> 8) + 128) as u8));
+
+        let beats = StreamAnalyzer::from_wav(Cursor::new(wav.clone()), StrategyKind::LPF)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(4, beats.len());
+
+        // the midpoint of unsigned samples is silence, not a large offset
+        wav.truncate(44);
+        wav.extend(vec![128; 44100]);
+        wav[40..44].copy_from_slice(&44100_u32.to_le_bytes());
+        let mut analyzer = StreamAnalyzer::from_wav(Cursor::new(wav), StrategyKind::LPF).unwrap();
+        assert!(analyzer.next().is_none());
+        assert_eq!(Some(0.0), analyzer.detector.last_analysis().intensity);
+    }
+
+    /// Delivers synthetic audio in blocks.
+    struct SynthSource {
+        samples: Vec<i16>,
+        pos: usize,
+    }
+
+    impl AudioSource for SynthSource {
+        fn next_block(&mut self) -> Option<&[i16]> {
+            let begin = self.pos;
+            self.pos = (self.pos + 1024).min(self.samples.len());
+            Some(&self.samples[begin..self.pos]).filter(|block| !block.is_empty())
+        }
+
+        fn sampling_rate(&self) -> u32 {
+            44100
+        }
+    }
+
+    #[test]
+    fn test_listen_audio_source() {
+        let source = SynthSource {
+            samples: synth_beats(44100, 120.0, 4, 50.0),
+            pos: 0,
+        };
+        let mut beats = Vec::new();
+        listen(source, StrategyKind::LPF, |info| beats.push(info));
+        assert_eq!(4, beats.len());
+    }
+
+    #[test]
+    fn test_hop_analyzer_irregular_chunks() {
+        let samples = synth_beats(44100, 120.0, 4, 50.0);
+        let detector = StrategyKind::LPF.detector(44100);
+        let expected = samples
+            .chunks_exact(512)
+            .filter_map(|window| detector.is_beat(window))
+            .map(|info| info.relative_ms())
+            .collect::<Vec<_>>();
+
+        let mut analyzer = HopAnalyzer::new(StrategyKind::LPF, 44100, 512);
+        let mut beats = Vec::new();
+        // irregular chunk sizes as some audio drivers deliver them
+        let mut rest = samples.as_slice();
+        for len in [480, 7, 1000, 3, 64, 1].iter().cycle() {
+            let (chunk, tail) = rest.split_at((*len).min(rest.len()));
+            analyzer.process(chunk, |info| beats.push(info.relative_ms()));
+            rest = tail;
+            if rest.is_empty() {
+                break;
+            }
+        }
+        assert_eq!(expected, beats);
+        assert_eq!(4, beats.len());
+        assert_eq!(samples.len() % 512, analyzer.pending_samples());
+    }
+
+    #[test]
+    fn test_analyze_offline() {
+        let samples = synth_beats(44100, 120.0, 8, 50.0);
+        let beats = analyze_offline(&samples, 44100, StrategyKind::LPF);
+        assert_eq!(8, beats.len());
+
+        // same result as a stream that is chunked irregularly
+        let mut analyzer = HopAnalyzer::new(StrategyKind::LPF, 44100, BLOCK_LEN);
+        let mut streamed = Vec::new();
+        let mut rest = samples.as_slice();
+        for chunk_len in [100, 700, 3000, 1].iter().cycle() {
+            let (chunk, tail) = rest.split_at((*chunk_len).min(rest.len()));
+            analyzer.process(chunk, |info| streamed.push(info));
+            rest = tail;
+            if rest.is_empty() {
+                break;
+            }
+        }
+        let times = |beats: &[BeatInfo]| beats.iter().map(|b| b.relative_ms()).collect::<Vec<_>>();
+        assert_eq!(times(&streamed), times(&beats));
+        assert_eq!(
+            times(&beats),
+            times(&analyze_offline(&samples, 44100, StrategyKind::LPF))
+        );
+    }
+
+    #[test]
+    fn test_fixed_window_analyzer() {
+        // two seconds plus a trailing incomplete hop
+        let mut samples = synth_beats(44100, 120.0, 3, 50.0);
+        samples.resize(88200 + 100, 0);
+        let mut analyzer = FixedWindowAnalyzer::new(StrategyKind::LPF, 44100, 1024);
+        let mut frames = Vec::new();
+        for chunk in samples.chunks(700) {
+            frames.extend(analyzer.frames(chunk));
+        }
+        assert_eq!(88300 / 1024, frames.len());
+        assert_eq!(frames.len() * 1024, analyzer.detector().samples_processed());
+
+        // the middle of the first window is at 512 samples, i.e. 11.6ms
+        assert_eq!(11, frames[0].time_ms);
+        assert_eq!(512, frames[0].sample_index);
+        assert!(frames.windows(2).all(|w| w[0].time_ms < w[1].time_ms));
+        assert!(frames
+            .iter()
+            .enumerate()
+            .all(|(i, frame)| frame.sample_index == i * 1024 + 512));
+
+        // the frames of beats have the times of the beats
+        let beats = analyze_offline(&samples, 44100, StrategyKind::LPF);
+        let beat_frames = frames.iter().filter(|frame| frame.beat).collect::<Vec<_>>();
+        assert_eq!(3, beat_frames.len());
+        for (frame, beat) in beat_frames.iter().zip(beats.iter()) {
+            assert_eq!(beat.relative_ms(), frame.time_ms);
+            assert_eq!(beat.sample_index(), frame.sample_index);
+        }
+
+        // the time origin of the detector applies to the frames as well
+        let mut shifted = FixedWindowAnalyzer::new(StrategyKind::LPF, 44100, 1024);
+        shifted.detector().set_time_origin(10_000);
+        let shifted_frames = shifted.frames(&samples);
+        for (frame, shifted) in frames.iter().zip(shifted_frames.iter()) {
+            assert_eq!(frame.time_ms + 10_000, shifted.time_ms);
+            assert_eq!(frame.sample_index, shifted.sample_index);
+        }
+        for frame in &frames {
+            assert_eq!(frame.beat, frame.outcome == AnalysisOutcome::Beat);
+            assert!((0.0..=1.0).contains(&frame.energy));
+            if frame.beat {
+                assert!(frame.energy > 0.1);
+            }
+        }
+        // the silence after the last beat
+        assert_eq!(0.0, frames.last().unwrap().energy);
+    }
+
+    #[test]
+    fn test_beat_queue() {
+        let (producer, consumer) = beat_queue(2);
+        assert!(consumer.pop().is_none());
+        producer.push(BeatInfo::new(1, 0.1)).unwrap();
+        producer.push(BeatInfo::new(2, 0.2)).unwrap();
+        assert_eq!(
+            3,
+            producer
+                .push(BeatInfo::new(3, 0.3))
+                .unwrap_err()
+                .relative_ms()
+        );
+        assert_eq!(Some(1), consumer.pop().map(|info| info.relative_ms()));
+        producer.push(BeatInfo::new(4, 0.4)).unwrap();
+        let drained = consumer
+            .drain()
+            .map(|info| info.relative_ms())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![2, 4], drained);
+
+        // across threads, nothing is lost or reordered if the consumer keeps up
+        let handle = std::thread::spawn(move || {
+            for ms in 0..1000 {
+                while producer.push(BeatInfo::new(ms, 0.5)).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut received = Vec::new();
+        while received.len() < 1000 {
+            received.extend(consumer.drain().map(|info| info.relative_ms()));
+        }
+        handle.join().unwrap();
+        assert_eq!((0..1000).collect::<Vec<_>>(), received);
+    }
+}
@@ -42,45 +42,336 @@ clippy::nursery,
 use crate::strategies::lpf::LpfBeatDetector;
 use crate::strategies::spectrum::SABeatDetector;
 use crate::strategies::window_stats::WindowStats;
-use crate::strategies::AnalysisState;
+pub use crate::strategies::AnalysisState;
+use std::fmt::{self, Display, Formatter};
 
+/// Highest frequency a strategy may look at relative to the sampling rate, see
+/// [`StrategyKind::validate_sampling_rate`].
+const MAX_RELATIVE_FREQUENCY: f32 = 0.4;
+
+#[cfg(feature = "plot")]
+pub mod debug;
+pub mod export;
+pub mod io;
 pub mod record;
 mod strategies;
+pub mod tempo;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 /// Struct that holds information about a detected beat.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct BeatInfo {
     relative_ms: u32,
-    // todo intensity
+    /// Maximum amplitude of the audio window of the beat in range `[0, 1]`.
+    intensity: f32,
+    /// Approximated spectral centroid of the audio window in range `[0, 1]`.
+    brightness: f32,
+    /// Index of the beat's sample since the beginning of the stream.
+    sample_index: usize,
 }
 impl BeatInfo {
+    /// Constructor. The intensity is clamped into the range `[0, 1]`; NaN
+    /// becomes `0`.
+    #[inline(always)]
+    pub fn new(relative_ms: u32, intensity: f32) -> Self {
+        Self {
+            relative_ms,
+            intensity: clamp_unit(intensity),
+            brightness: 0.0,
+            sample_index: 0,
+        }
+    }
+
+    /// Sets the brightness, see [`BeatInfo::brightness`]. It is clamped into
+    /// the range `[0, 1]`; NaN becomes `0`.
     #[inline(always)]
-    pub const fn new(relative_ms: u32) -> Self {
-        Self { relative_ms }
+    pub fn with_brightness(mut self, brightness: f32) -> Self {
+        self.brightness = clamp_unit(brightness);
+        self
+    }
+
+    /// Like [`Self::new`] but returns `None` if the intensity is not in range `[0, 1]`
+    /// instead of clamping it.
+    #[inline(always)]
+    pub fn try_new(relative_ms: u32, intensity: f32) -> Option<Self> {
+        if (0.0..=1.0).contains(&intensity) {
+            Some(Self {
+                relative_ms,
+                intensity,
+                brightness: 0.0,
+                sample_index: 0,
+            })
+        } else {
+            None
+        }
     }
 
     #[inline(always)]
     pub const fn relative_ms(&self) -> u32 {
         self.relative_ms
     }
+
+    #[inline(always)]
+    pub const fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// Sets the sample index, see [`BeatInfo::sample_index`].
+    #[inline(always)]
+    pub const fn with_sample_index(mut self, sample_index: usize) -> Self {
+        self.sample_index = sample_index;
+        self
+    }
+
+    /// Index of the (mono) sample of the beat since the beginning of the stream,
+    /// e.g. for DAW or sample editor integration. Like [`BeatInfo::relative_ms`],
+    /// it points to the middle of the analyzed window. In contrast to the time,
+    /// it doesn't accumulate rounding errors and is not affected by
+    /// [`Strategy::set_time_origin`].
+    #[inline(always)]
+    pub const fn sample_index(&self) -> usize {
+        self.sample_index
+    }
+
+    /// Approximated spectral centroid of the audio window of the beat in range
+    /// `[0, 1]`, derived from the zero crossing rate of the unfiltered signal.
+    /// `1` corresponds to half the sampling rate. Dark hits like kicks are close
+    /// to `0`, bright hits like hi-hats have considerably higher values.
+    #[inline(always)]
+    pub const fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    /// Multiplies the time and the sample index of the beat by `factor`.
+    ///
+    /// This maps beats from the timeline of the analyzed audio to the timeline of
+    /// a time-stretched playback, e.g. `0.5` for audio that is played twice as
+    /// fast with a DJ pitch fader. Panics if `factor` isn't a positive number.
+    pub fn rescale_time(&self, factor: f32) -> Self {
+        assert!(
+            factor.is_finite() && factor > 0.0,
+            "The factor must be a positive number!"
+        );
+        let factor = factor as f64;
+        Self {
+            relative_ms: (self.relative_ms as f64 * factor).round() as u32,
+            sample_index: (self.sample_index as f64 * factor).round() as usize,
+            ..*self
+        }
+    }
+
+    /// Like [`BeatInfo::rescale_time`] but for multiple beats.
+    pub fn rescale_times(beats: &[Self], factor: f32) -> Vec<Self> {
+        beats.iter().map(|beat| beat.rescale_time(factor)).collect()
+    }
+}
+
+/// Clamps a value into the range `[0, 1]`. Unlike [`f32::clamp`], NaN becomes `0`.
+#[inline(always)]
+fn clamp_unit(value: f32) -> f32 {
+    if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, 1.0)
+    }
+}
+
+impl Display for BeatInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "beat @ {}ms, intensity={:.3}",
+            self.relative_ms, self.intensity
+        )
+    }
+}
+
+/// Why the latest window was or wasn't a beat, see [`LastAnalysis`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisOutcome {
+    /// No window was analyzed yet.
+    NotAnalyzed,
+    /// The window is too close to the previous beat, see
+    /// [`Strategy::min_duration_between_beats_ms`].
+    TooCloseToLastBeat,
+    /// The maximum amplitude of the window is too low, e.g. silence or noise.
+    AmplitudeTooLow,
+    /// The strategy specific criterion didn't reach its threshold.
+    BelowCriteria,
+    /// The window contains a beat.
+    Beat,
+}
+
+/// Diagnostics about the analysis of the latest window.
+///
+/// Retrievable via [`Strategy::last_analysis`]. Turns a `None` from
+/// [`Strategy::is_beat`] into something actionable when a loud hit isn't detected.
+/// Values the analysis didn't get to are `None`.
+#[derive(Debug, Clone, Copy)]
+pub struct LastAnalysis {
+    /// The reason for the decision.
+    pub outcome: AnalysisOutcome,
+    /// Maximum amplitude of the window in range `[0, 1]`.
+    pub intensity: Option<f32>,
+    /// Value of the strategy specific criterion, e.g. the maximum amplitude after
+    /// the lowpass filter.
+    pub criterion: Option<f32>,
+    /// Threshold the criterion must reach for a beat.
+    pub threshold: Option<f32>,
+}
+
+impl LastAnalysis {
+    /// Value before the first window was analyzed.
+    pub const NOT_ANALYZED: Self = Self {
+        outcome: AnalysisOutcome::NotAnalyzed,
+        intensity: None,
+        criterion: None,
+        threshold: None,
+    };
 }
 
 /// Common abstraction over a beat detection strategy. Each strategy keeps ongoing
 /// audio samples, for example from microphone. Strategies should have an internal
 /// mutable state via interior mutability to compare sample windows (and analysis)
 /// against previous values.
-pub trait Strategy {
+pub trait Strategy: std::fmt::Debug {
     /// Checks if inside the samples window a new beat was recognized.
     /// If so, it returns `Some` with [`BeatInfo`] as payload.
     ///
     /// Implementations may buffer previous samples and combine them with the latest,
     /// i.e. make a sliding window.
+    ///
+    /// An empty window is ignored and returns `None`. Windows should cover at least
+    /// a few milliseconds of audio; to analyze tiny chunks from low latency audio
+    /// configs, collect them with [`io::HopAnalyzer`] first.
+    ///
+    /// `None` doesn't tell why there is no beat, e.g. because the audio was too
+    /// quiet or the last beat is too recent; see [`Strategy::last_analysis`].
+    #[must_use = "a beat is lost if the result is ignored"]
     fn is_beat(&self, samples: &[i16]) -> Option<BeatInfo>;
 
+    /// Like [`Strategy::is_beat`] but for audio data in range `[-1, 1]`, as
+    /// many audio devices deliver it. The samples are scaled to `i16` internally.
+    #[inline(always)]
+    #[must_use = "a beat is lost if the result is ignored"]
+    fn is_beat_f32(&self, samples: &[f32]) -> Option<BeatInfo> {
+        self.is_beat(&record::f32_data_to_i16(samples))
+    }
+
+    /// Like [`Strategy::is_beat`] but for interleaved audio data with `channels`
+    /// channels, e.g. from a multichannel WAV file. The channels are mixed down
+    /// to mono by their mean, see [`record::downmix_interleaved`].
+    #[inline(always)]
+    #[must_use = "a beat is lost if the result is ignored"]
+    fn is_beat_interleaved(&self, samples: &[i16], channels: u16) -> Option<BeatInfo> {
+        if channels <= 1 {
+            return self.is_beat(samples);
+        }
+        self.is_beat(&record::downmix_interleaved(samples, channels))
+    }
+
+    /// Like [`Strategy::is_beat_f32`] but for `f64` audio data. Values are clamped
+    /// to `[-1, 1]` before they are narrowed, because the analysis works on `i16`
+    /// internally. Precision beyond 16 bit is lost, which doesn't matter for
+    /// beat detection.
+    #[inline(always)]
+    #[must_use = "a beat is lost if the result is ignored"]
+    fn is_beat_f64(&self, samples: &[f64]) -> Option<BeatInfo> {
+        self.is_beat(
+            &samples
+                .iter()
+                .map(|x| record::f32_sample_to_i16(x.clamp(-1.0, 1.0) as f32))
+                .collect::<Vec<_>>(),
+        )
+    }
+
     /// Convenient getter to get the [`StrategyKind`] of a strategy.
     /// This is a 1:1 mapping.
     fn kind(&self) -> StrategyKind;
 
+    /// Getter for the internal [`AnalysisState`] of a strategy.
+    fn state(&self) -> &AnalysisState;
+
+    /// Clones the detector including its whole state, e.g. to snapshot it or to
+    /// compare two setups on the same audio. Used by `Clone` for `Box<dyn Strategy>`.
+    fn boxed_clone(&self) -> Box<dyn Strategy + Send>;
+
+    /// Total amount of (mono) samples this detector has analyzed so far.
+    /// Useful for progress bars during offline analysis.
+    #[inline(always)]
+    fn samples_processed(&self) -> usize {
+        self.state().samples_processed()
+    }
+
+    /// Duration in ms of all audio this detector has analyzed so far.
+    #[inline(always)]
+    fn elapsed_audio_time_ms(&self) -> u32 {
+        self.state().elapsed_audio_time_ms()
+    }
+
+    /// Upper bound in ms for how far a reported beat lags behind the audio.
+    ///
+    /// Useful to pre-delay visuals for A/V sync. A beat anywhere inside a window
+    /// can only be reported after the whole window arrived, so the estimate is the
+    /// duration of the latest window. Buffering inside the audio driver and device
+    /// comes on top of this and is not known here.
+    #[inline(always)]
+    fn detection_latency_ms(&self) -> u32 {
+        self.state().detection_latency_ms()
+    }
+
+    /// Continuous value in range `[0, 1]` that rises towards `1` around a beat and
+    /// decays between beats.
+    ///
+    /// It is the maximum amplitude of the latest window relative to the recent
+    /// maximum amplitude and is updated with each window. Useful to drive
+    /// parameters smoothly instead of with hard flashes on each [`BeatInfo`].
+    #[inline(always)]
+    fn beat_activation(&self) -> f32 {
+        self.state().beat_activation()
+    }
+
+    /// Whether the audio of at least the last `min_duration_ms` was silent, e.g.
+    /// to let a light show fade to idle when the music stops.
+    #[inline(always)]
+    fn is_silent(&self, min_duration_ms: u32) -> bool {
+        self.state().is_silent(min_duration_ms)
+    }
+
+    /// Time in ms since the latest beat or `None` if there was no beat yet.
+    #[inline(always)]
+    fn time_since_last_beat_ms(&self) -> Option<u32> {
+        self.state().time_since_last_beat_ms()
+    }
+
+    /// Diagnostics about the analysis of the latest window, e.g. to find out why a
+    /// beat wasn't detected.
+    #[inline(always)]
+    fn last_analysis(&self) -> LastAnalysis {
+        self.state().last_analysis()
+    }
+
+    /// Sets the sampling rate of a detector that was created via
+    /// [`StrategyKind::detector_uninitialized`]. This must happen before the
+    /// first call to [`Strategy::is_beat`], otherwise it panics. Implementations
+    /// forward this to [`AnalysisState::set_sampling_rate`].
+    fn set_sampling_rate(&mut self, sampling_rate: u32);
+
+    /// Sets the timestamp in ms that the audio analyzed next starts at, e.g. the
+    /// start of a new clip.
+    ///
+    /// Subsequent [`BeatInfo::relative_ms`] values are relative to it. This only
+    /// affects reported timestamps; the internal time, which is used to skip beats
+    /// that are too close to the previous one, keeps running. The origin stays in
+    /// effect until it is set again. Reported times saturate at `u32::MAX`.
+    #[inline(always)]
+    fn set_time_origin(&self, origin_ms: u32) {
+        self.state().set_time_origin(origin_ms)
+    }
+
     /// A nice name for the algorithm, displayable in user interfaces.
     // "where Self: Sized" => compiler gave me this hint
     // => prevents "`Strategy` cannot be made into an object"
@@ -136,6 +427,12 @@ pub trait Strategy {
     }
 }
 
+impl Clone for Box<dyn Strategy + Send> {
+    fn clone(&self) -> Self {
+        self.boxed_clone()
+    }
+}
+
 /// Enum that conveniently and easily makes all [`Strategy`]s provided by this crate accessible.
 /// This enum provides the bare minimum functionality to access the strategies. All deeper
 /// functionality must be defined inside the implementations.
@@ -160,6 +457,61 @@ impl StrategyKind {
         }
     }
 
+    /// Like [`StrategyKind::detector`] but checks first whether the strategy can
+    /// work with the sampling rate, see [`StrategyKind::validate_sampling_rate`].
+    pub fn try_detector(&self, sampling_rate: u32) -> Result<Box<dyn Strategy + Send>, String> {
+        self.validate_sampling_rate(sampling_rate)?;
+        Ok(self.detector(sampling_rate))
+    }
+
+    /// Checks whether the strategy can work with the sampling rate.
+    ///
+    /// The frequencies each strategy looks at must be comfortably below the Nyquist
+    /// frequency, i.e. half the sampling rate. Close to or above it, the filters
+    /// degenerate and produce garbage or the analysis panics. Hence, the highest
+    /// frequency must be below 40% of the sampling rate. Low rates like 8kHz
+    /// telephone audio are fine, because only low frequencies are relevant.
+    pub fn validate_sampling_rate(&self, sampling_rate: u32) -> Result<(), String> {
+        let highest_frequency = match self {
+            Self::LPF => strategies::lpf::CUTOFF_FR as f32,
+            Self::Spectrum => strategies::spectrum::MAX_FREQUENCY_HZ,
+            // _ => panic!("Unknown Strategy"),
+        };
+        let min_sampling_rate = highest_frequency / MAX_RELATIVE_FREQUENCY;
+        if sampling_rate as f32 <= min_sampling_rate {
+            return Err(format!(
+                "Sampling rate of {}Hz is too low for strategy {}: it looks at frequencies up to {}Hz, which requires more than {}Hz.",
+                sampling_rate,
+                self.name(),
+                highest_frequency,
+                min_sampling_rate
+            ));
+        }
+        // the lowpass filter takes the sampling rate as u16
+        if *self == Self::LPF && sampling_rate > u16::MAX as u32 {
+            return Err(format!(
+                "Sampling rate of {}Hz is too high for strategy {}: at most {}Hz are supported.",
+                sampling_rate,
+                self.name(),
+                u16::MAX
+            ));
+        }
+        Ok(())
+    }
+
+    /// Like [`StrategyKind::detector`] but for pipelines that don't know the
+    /// sampling rate yet, e.g. because it arrives with the first audio callback.
+    /// [`Strategy::set_sampling_rate`] must be called before the first call to
+    /// [`Strategy::is_beat`], otherwise it panics.
+    #[inline(always)]
+    pub fn detector_uninitialized(&self) -> Box<dyn Strategy + Send> {
+        match self {
+            Self::LPF => Box::new(LpfBeatDetector::uninitialized()),
+            Self::Spectrum => Box::new(SABeatDetector::uninitialized()),
+            // _ => panic!("Unknown Strategy"),
+        }
+    }
+
     /// Convenient wrapper for ['Strategy::name'].
     pub fn name(&self) -> &'static str {
         match self {
@@ -191,6 +543,312 @@ mod tests {
     use std::collections::HashMap;
     use std::fs::File;
 
+    #[test]
+    fn test_beat_info_display() {
+        assert_eq!(
+            "beat @ 2299ms, intensity=0.505",
+            BeatInfo::new(2299, 0.50512).to_string()
+        );
+    }
+
+    #[test]
+    fn test_validate_sampling_rate() {
+        for kind in StrategyKind::values() {
+            assert!(kind.try_detector(44100).is_ok());
+            assert!(kind.try_detector(0).is_err());
+            assert!(kind.try_detector(100).is_err());
+        }
+        // close to the Nyquist frequency
+        assert!(StrategyKind::LPF.validate_sampling_rate(250).is_err());
+        assert!(StrategyKind::LPF.validate_sampling_rate(300).is_err());
+        assert!(StrategyKind::LPF.validate_sampling_rate(301).is_ok());
+        assert!(StrategyKind::LPF.validate_sampling_rate(96000).is_err());
+        assert!(StrategyKind::Spectrum.validate_sampling_rate(200).is_err());
+        assert!(StrategyKind::Spectrum.validate_sampling_rate(226).is_ok());
+        assert!(StrategyKind::Spectrum.validate_sampling_rate(96000).is_ok());
+    }
+
+    #[test]
+    fn test_low_sampling_rates() {
+        for sampling_rate in [8000, 4000].iter().copied() {
+            let samples = testing::synth_beats(sampling_rate, 120.0, 8, 50.0);
+            let detector = StrategyKind::LPF.try_detector(sampling_rate).unwrap();
+            let beats = samples
+                .chunks(256)
+                .filter_map(|window| detector.is_beat(window))
+                .count();
+            assert_eq!(8, beats, "{}Hz", sampling_rate);
+            assert!(StrategyKind::Spectrum.try_detector(sampling_rate).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_last_analysis() {
+        let detector = StrategyKind::LPF.detector(44100);
+        assert_eq!(
+            AnalysisOutcome::NotAnalyzed,
+            detector.last_analysis().outcome
+        );
+
+        let samples = testing::synth_beats(44100, 60.0, 2, 50.0);
+        let mut outcomes = Vec::new();
+        for window in samples.chunks(1024) {
+            let is_beat = detector.is_beat(window).is_some();
+            let analysis = detector.last_analysis();
+            assert_eq!(is_beat, analysis.outcome == AnalysisOutcome::Beat);
+            if is_beat {
+                assert!(analysis.criterion.unwrap() >= analysis.threshold.unwrap());
+            }
+            outcomes.push(analysis.outcome);
+        }
+        // silence before the first hit after the initial dead time
+        assert_eq!(AnalysisOutcome::AmplitudeTooLow, outcomes[20]);
+        assert!(outcomes[20..].contains(&AnalysisOutcome::Beat));
+        // the window after a beat
+        let beat_idx = outcomes
+            .iter()
+            .position(|o| *o == AnalysisOutcome::Beat)
+            .unwrap();
+        assert_eq!(AnalysisOutcome::TooCloseToLastBeat, outcomes[beat_idx + 1]);
+    }
+
+    #[test]
+    fn test_beat_activation() {
+        let detector = StrategyKind::LPF.detector(44100);
+        assert_eq!(0.0, detector.beat_activation());
+
+        let samples = testing::synth_beats(44100, 60.0, 4, 50.0);
+        let mut beats = 0;
+        for (i, window) in samples.chunks(1024).enumerate() {
+            let is_beat = detector.is_beat(window).is_some();
+            let activation = detector.beat_activation();
+            assert!((0.0..=1.0).contains(&activation));
+            if is_beat {
+                beats += 1;
+                assert!(activation > 0.9, "activation at beat is {}", activation);
+            }
+            // half-way between two hits
+            let ms = (i * 1024 + 512) as u32 * 1000 / 44100;
+            if ms > 1000 && ms % 1000 > 450 && ms % 1000 < 550 {
+                assert!(
+                    activation < 0.3,
+                    "activation between beats is {}",
+                    activation
+                );
+            }
+        }
+        assert_eq!(4, beats);
+    }
+
+    #[test]
+    fn test_small_and_empty_windows() {
+        for kind in StrategyKind::values() {
+            let detector = kind.detector(44100);
+            assert!(detector.is_beat(&[]).is_none());
+            assert_eq!(0, detector.samples_processed());
+            assert_eq!(
+                AnalysisOutcome::NotAnalyzed,
+                detector.last_analysis().outcome
+            );
+            for len in [1, 5].iter() {
+                let _ = detector.is_beat(&vec![i16::MAX; *len]);
+            }
+            assert_eq!(6, detector.samples_processed());
+        }
+
+        // tiny chunks are collected and analyzed once enough samples are available
+        let samples = testing::synth_beats(44100, 120.0, 4, 50.0);
+        let mut analyzer = io::HopAnalyzer::new(StrategyKind::LPF, 44100, 1024);
+        let mut beats = 0;
+        for chunk in samples.chunks(5) {
+            analyzer.process(&[], |_| beats += 1);
+            analyzer.process(chunk, |_| beats += 1);
+        }
+        assert_eq!(4, beats);
+    }
+
+    #[test]
+    fn test_is_beat_f64() {
+        let samples = testing::synth_beats(44100, 120.0, 4, 50.0)
+            .iter()
+            .map(|x| *x as f64 / i16::MAX as f64)
+            .collect::<Vec<_>>();
+        let detector_f64 = StrategyKind::LPF.detector(44100);
+        let detector_f32 = StrategyKind::LPF.detector(44100);
+        let mut beats = 0;
+        for window in samples.chunks(1024) {
+            let window_f32 = window.iter().map(|x| *x as f32).collect::<Vec<_>>();
+            let beat_f64 = detector_f64.is_beat_f64(window).map(|b| b.relative_ms());
+            let beat_f32 = detector_f32
+                .is_beat_f32(&window_f32)
+                .map(|b| b.relative_ms());
+            assert_eq!(beat_f32, beat_f64);
+            beats += beat_f64.iter().count();
+        }
+        assert_eq!(4, beats);
+        // out of range values are clamped
+        assert!(detector_f64.is_beat_f64(&[2.0, -2.0, f64::NAN]).is_none());
+    }
+
+    #[test]
+    fn test_silence_detection() {
+        let detector = StrategyKind::LPF.detector(44100);
+        assert!(!detector.is_silent(1));
+        assert_eq!(None, detector.time_since_last_beat_ms());
+
+        let mut samples = testing::synth_beats(44100, 120.0, 4, 50.0);
+        let beats_len = samples.len();
+        // 3s silence
+        samples.extend(vec![0; 3 * 44100]);
+        let mut silence_begin = None;
+        for (i, window) in samples.chunks(1024).enumerate() {
+            let _ = detector.is_beat(window);
+            if silence_begin.is_none() && detector.is_silent(1000) {
+                silence_begin = Some(i * 1024);
+            }
+        }
+        // last hit decays within ~150ms after 2000ms, silence is detected 1s later
+        let silence_begin_ms = silence_begin.unwrap() * 1000 / 44100;
+        assert!(
+            silence_begin_ms > beats_len * 1000 / 44100,
+            "silence detected at {}ms",
+            silence_begin_ms
+        );
+        assert!(
+            silence_begin_ms < 3400,
+            "silence detected at {}ms",
+            silence_begin_ms
+        );
+        // last beat at 2000ms, end at 5500ms
+        let since_beat = detector.time_since_last_beat_ms().unwrap();
+        assert!((since_beat as i32 - 3500).abs() < 50, "{}ms", since_beat);
+    }
+
+    #[test]
+    fn test_detector_uninitialized() {
+        for kind in StrategyKind::values() {
+            let mut detector = kind.detector_uninitialized();
+            assert!(!detector.state().is_initialized());
+            detector.set_sampling_rate(44100);
+            assert_eq!(44100, detector.state().sampling_rate());
+            let beats = testing::synth_beats(44100, 120.0, 2, 50.0)
+                .chunks(1024)
+                .filter_map(|window| detector.is_beat(window))
+                .count();
+            assert!(beats <= 2);
+        }
+    }
+
+    #[test]
+    fn test_clone_detector() {
+        let samples = testing::synth_beats(44100, 120.0, 8, 50.0);
+        let (warm_up, rest) = samples.split_at(samples.len() / 2 + 300);
+        for kind in StrategyKind::values() {
+            let detector = kind.detector(44100);
+            warm_up.chunks(1024).for_each(|window| {
+                let _ = detector.is_beat(window);
+            });
+            let clone = detector.clone();
+            assert_eq!(detector.samples_processed(), clone.samples_processed());
+
+            let beats = rest
+                .chunks(1024)
+                .map(|window| detector.is_beat(window).map(|b| b.relative_ms()))
+                .collect::<Vec<_>>();
+            let beats_clone = rest
+                .chunks(1024)
+                .map(|window| clone.is_beat(window).map(|b| b.relative_ms()))
+                .collect::<Vec<_>>();
+            assert_eq!(beats, beats_clone);
+            if kind == StrategyKind::LPF {
+                assert_eq!(4, beats.iter().flatten().count());
+            }
+        }
+    }
+
+    #[test]
+    fn test_beat_info_sample_index() {
+        let samples = testing::synth_beats(44100, 120.0, 8, 50.0);
+        let detector = StrategyKind::LPF.detector(44100);
+        let beats = samples
+            .chunks(1024)
+            .filter_map(|window| detector.is_beat(window))
+            .collect::<Vec<_>>();
+        assert_eq!(8, beats.len());
+        for (i, beat) in beats.iter().enumerate() {
+            // in the middle of the window of the hit
+            let expected = (i + 1) * 22050 / 1024 * 1024 + 512;
+            assert_eq!(expected, beat.sample_index());
+            // unlike the time in ms, the index doesn't drift due to rounding
+            let ms = beat.sample_index() * 1000 / 44100;
+            assert!((ms as i64 - (i as i64 + 1) * 500).abs() < 25, "{}ms", ms);
+            assert!(beat.relative_ms() as usize <= ms);
+        }
+    }
+
+    #[test]
+    fn test_beat_info_rescale_time() {
+        let beats = (1..=8)
+            .map(|n| BeatInfo::new(n * 500, 0.5).with_sample_index(n as usize * 22050))
+            .collect::<Vec<_>>();
+        let stretched = BeatInfo::rescale_times(&beats, 0.5);
+        for (beat, stretched) in beats.iter().zip(stretched.iter()) {
+            assert_eq!(beat.relative_ms() / 2, stretched.relative_ms());
+            assert_eq!(beat.sample_index() / 2, stretched.sample_index());
+            assert_eq!(beat.intensity(), stretched.intensity());
+        }
+
+        let bpm_of = |beats: &[BeatInfo]| {
+            let mut tracker = tempo::TempoTracker::new();
+            beats.iter().for_each(|beat| tracker.on_beat(beat));
+            tracker.bpm().unwrap()
+        };
+        assert!((bpm_of(&beats) - 120.0).abs() < 0.01);
+        assert!((bpm_of(&stretched) - 240.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_is_beat_interleaved() {
+        let mono = testing::synth_beats(44100, 120.0, 4, 50.0);
+        // right channel is quieter
+        let stereo = mono
+            .iter()
+            .flat_map(|x| vec![*x, *x / 2])
+            .collect::<Vec<_>>();
+        let downmixed = record::downmix_interleaved(&stereo, 2);
+
+        let detector = StrategyKind::LPF.detector(44100);
+        let detector_downmixed = StrategyKind::LPF.detector(44100);
+        let mut beats = 0;
+        for (interleaved, mono) in stereo.chunks(2 * 1024).zip(downmixed.chunks(1024)) {
+            let beat = detector.is_beat_interleaved(interleaved, 2);
+            let beat_downmixed = detector_downmixed.is_beat(mono);
+            assert_eq!(
+                beat_downmixed.map(|b| b.relative_ms()),
+                beat.map(|b| b.relative_ms())
+            );
+            beats += beat.iter().count();
+        }
+        assert_eq!(4, beats);
+        assert_eq!(downmixed.len(), detector.samples_processed());
+    }
+
+    #[test]
+    fn test_beat_info_intensity_range() {
+        assert_eq!(1.0, BeatInfo::new(0, 1.3).intensity());
+        assert_eq!(0.0, BeatInfo::new(0, -0.1).intensity());
+        assert_eq!(0.15, BeatInfo::new(0, 0.15).intensity());
+        assert!(BeatInfo::try_new(0, 1.3).is_none());
+        assert!(BeatInfo::try_new(0, -0.1).is_none());
+        assert!(BeatInfo::try_new(0, f32::NAN).is_none());
+        assert_eq!(0.15, BeatInfo::try_new(0, 0.15).unwrap().intensity());
+        assert_eq!(0.0, BeatInfo::new(0, f32::NAN).intensity());
+        assert_eq!(1.0, BeatInfo::new(0, f32::INFINITY).intensity());
+        let beat = BeatInfo::new(0, 0.5);
+        assert_eq!(1.0, beat.with_brightness(1.3).brightness());
+        assert_eq!(0.0, beat.with_brightness(f32::NAN).brightness());
+    }
+
     // opened the file in Audacity and looked where the
     // beats are
     const SAMPLE_1_EXPECTED_BEATS_MS: [u32; 6] = [300, 2131, 2297, 4303, 6143, 6310];
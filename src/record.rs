@@ -24,28 +24,557 @@ SOFTWARE.
 //! Module for audio recording from an audio input device.
 //! This needs `std`-functionality.
 
-use crate::{BeatInfo, StrategyKind};
+use crate::io::AudioSource;
+use crate::io::{beat_queue, BeatConsumer};
+#[cfg(feature = "wav")]
+use crate::io::{StreamAnalyzer, WavWriter};
+use crate::tempo::TempoTracker;
+use crate::{export, BeatInfo, Strategy, StrategyKind};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{BufferSize, Device, Host, InputCallbackInfo, SampleFormat, StreamConfig, StreamError};
+use cpal::{
+    BufferSize, Device, Host, InputCallbackInfo, SampleFormat, Stream, StreamConfig, StreamError,
+    SupportedBufferSize,
+};
 use std::collections::{BTreeMap, HashMap};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::fmt::{self, Debug, Formatter};
+#[cfg(feature = "wav")]
+use std::fs::File;
+use std::io::Write;
+#[cfg(feature = "wav")]
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind};
+use std::net::{SocketAddr, UdpSocket};
+#[cfg(feature = "wav")]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, TrySendError};
 use std::sync::Arc;
 use std::thread::{spawn, JoinHandle};
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Starts listening to audio events and tries to recognize beats
 /// on the audio. On each recognized beat, the specified callback
 /// is executed. It does so by starting a new thread.
+///
+/// See [`start_listening_with_options`] for pausing, custom downmixing, silence
+/// detection and more.
 pub fn start_listening(
     on_beat_cb: impl Fn(BeatInfo) + Send + 'static,
     input_dev: Option<Device>,
     strategy: StrategyKind,
     keep_recording: Arc<AtomicBool>,
 ) -> Result<JoinHandle<()>, String> {
+    start_listening_with_options(
+        ListenOptions::new(input_dev, strategy, keep_recording),
+        move |info, _instant| on_beat_cb(info),
+    )
+}
+
+/// Maps an interleaved frame to a mono sample, see [`ListenOptions::with_downmix`].
+type DownmixFn = Box<dyn Fn(&[i16]) -> i16 + Send>;
+
+/// See [`ListenOptions::with_silence`].
+type SilenceCallback = Box<dyn Fn(bool) + Send>;
+
+/// Options for [`start_listening_with_options`], [`start_listening_queued`] and
+/// [`start_listening_jsonl`]. All options can be combined.
+///
+/// ```no_run
+/// use beat_detector::record::{start_listening_with_options, ListenOptions};
+/// use beat_detector::StrategyKind;
+/// use std::sync::atomic::AtomicBool;
+/// use std::sync::Arc;
+///
+/// let keep_recording = Arc::new(AtomicBool::new(true));
+/// let paused = Arc::new(AtomicBool::new(false));
+/// let options = ListenOptions::new(None, StrategyKind::Spectrum, keep_recording)
+///     .with_pause(paused)
+///     .with_silence(2000, |is_silent| println!("silent: {}", is_silent));
+/// let handle = start_listening_with_options(options, |info, _instant| println!("{}", info));
+/// ```
+pub struct ListenOptions {
+    input_dev: Option<Device>,
+    strategy: StrategyKind,
+    keep_recording: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    downmix: Option<DownmixFn>,
+    silence: Option<(u32, SilenceCallback)>,
+    #[cfg(feature = "wav")]
+    wav_path: Option<PathBuf>,
+}
+
+impl ListenOptions {
+    /// Constructor for [`ListenOptions`]. Listens on the input device (the
+    /// default device if `input_dev` is `None`) until `keep_recording` is set
+    /// to `false`.
+    pub fn new(
+        input_dev: Option<Device>,
+        strategy: StrategyKind,
+        keep_recording: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            input_dev,
+            strategy,
+            keep_recording,
+            paused: Arc::new(AtomicBool::new(false)),
+            downmix: None,
+            silence: None,
+            #[cfg(feature = "wav")]
+            wav_path: None,
+        }
+    }
+
+    /// The detection can be paused and resumed via `paused` without tearing down
+    /// the audio stream, e.g. for a "mute" toggle.
+    ///
+    /// While paused, no beats are reported and the audio isn't analyzed, which saves
+    /// CPU. The audio is still consumed and the time of the detector keeps running,
+    /// i.e. beats after resuming have the same timestamps as if the detection was
+    /// never paused. Beats directly after resuming may be missed until the detector
+    /// has seen enough new audio, e.g. the spectrum strategy needs 1024 samples.
+    pub fn with_pause(mut self, paused: Arc<AtomicBool>) -> Self {
+        self.paused = paused;
+        self
+    }
+
+    /// Custom function to mix multichannel audio down to mono, e.g. for M/S
+    /// decoding or to weight channels differently. By default, the mean of all
+    /// channels is used.
+    ///
+    /// `downmix` gets each interleaved frame, i.e. one sample per channel, and
+    /// returns the mono sample. It runs for each frame inside the audio callback,
+    /// so it must be fast and shouldn't allocate.
+    pub fn with_downmix(mut self, downmix: impl Fn(&[i16]) -> i16 + Send + 'static) -> Self {
+        self.downmix = Some(Box::new(downmix));
+        self
+    }
+
+    /// Additionally reports when the music stops.
+    ///
+    /// `on_silence_cb` is called with `true` when the audio was silent for at least
+    /// `silence_ms` (see [`Strategy::is_silent`]) and with `false` when audio comes
+    /// back, e.g. to let a light show fade to idle while the DJ pauses.
+    pub fn with_silence(
+        mut self,
+        silence_ms: u32,
+        on_silence_cb: impl Fn(bool) + Send + 'static,
+    ) -> Self {
+        self.silence = Some((silence_ms, Box::new(on_silence_cb)));
+        self
+    }
+
+    /// Additionally writes all audio that the detector sees (mono, after
+    /// downmixing) to a WAV file.
+    ///
+    /// This makes bug reports like
+    /// "beats aren't detected on my setup" reproducible: the file can be fed through
+    /// a detector again with [`replay_wav`]. The detector results depend on the
+    /// sizes of the analyzed windows, i.e. the chunks the audio driver delivers,
+    /// so their lengths are written to a second file next to the WAV file, see
+    /// [`chunk_lens_path`]. Audio that arrives while the detection is paused is
+    /// recorded, too, but replayed without pause.
+    ///
+    /// The files are written inside the audio callback; this is meant for debugging only.
+    /// Writing stops when the WAV file reaches the 4 GiB limit of WAV files.
+    #[cfg(feature = "wav")]
+    pub fn with_wav_recording(mut self, wav_path: impl AsRef<Path>) -> Self {
+        self.wav_path = Some(wav_path.as_ref().to_path_buf());
+        self
+    }
+}
+
+impl Debug for ListenOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("ListenOptions");
+        debug
+            .field("strategy", &self.strategy)
+            .field("keep_recording", &self.keep_recording)
+            .field("paused", &self.paused)
+            .field("downmix", &self.downmix.is_some())
+            .field("silence_ms", &self.silence.as_ref().map(|(ms, _)| ms));
+        #[cfg(feature = "wav")]
+        debug.field("wav_path", &self.wav_path);
+        debug.finish()
+    }
+}
+
+/// Like [`start_listening`] but with [`ListenOptions`]. The callback
+/// additionally gets the wall clock time of each beat, e.g. to correlate beats
+/// with MIDI or network events.
+///
+/// The time is anchored at the first audio callback: the instant of the first
+/// sample is the arrival time of the first buffer minus its duration. The beat
+/// time is relative to that. The latency between a beat physically occurring
+/// and its audio buffer arriving (driver and device buffering) is inherent and
+/// not compensated, i.e. the instant is always a bit later than the real event.
+pub fn start_listening_with_options(
+    options: ListenOptions,
+    on_beat_cb: impl Fn(BeatInfo, Instant) + Send + 'static,
+) -> Result<JoinHandle<()>, String> {
+    let ListenOptions {
+        input_dev,
+        strategy,
+        keep_recording,
+        paused,
+        downmix,
+        silence,
+        #[cfg(feature = "wav")]
+        wav_path,
+    } = options;
     if !keep_recording.load(Ordering::SeqCst) {
         return Err("Variable keep_recording is false from the beginning!?".to_string());
     }
 
+    let (in_dev, in_stream_cfg, sample_format) = open_input_device(input_dev)?;
+    let channels = in_stream_cfg.channels;
+    let sampling_rate = in_stream_cfg.sample_rate;
+
+    #[cfg(feature = "wav")]
+    let mut wav_recording = wav_path
+        .map(|wav_path| WavRecording::create(&wav_path, sampling_rate.0))
+        .transpose()?;
+
+    let detector = strategy.detector(sampling_rate.0);
+
+    let handle = spawn(move || {
+        // wall clock time of the first sample
+        let mut audio_begin = None;
+        let mut was_silent = false;
+        // reused for each callback to prevent allocations in the audio thread
+        let mut samples = Vec::new();
+        let on_new_audio = move |data: &[i16]| {
+            let now = Instant::now();
+            match &downmix {
+                Some(downmix) => {
+                    samples.clear();
+                    samples.extend(data.chunks_exact(channels as usize).map(downmix));
+                }
+                None => downmix_interleaved_into(data, channels, &mut samples),
+            }
+            #[cfg(feature = "wav")]
+            if let Some(recording) = wav_recording.as_mut() {
+                if let Err(err) = recording.write_chunk(&samples) {
+                    eprintln!("Can't write WAV file, stop recording: {:?}", err);
+                    // completes the header of the audio so far
+                    wav_recording = None;
+                }
+            }
+            let audio_begin = *audio_begin.get_or_insert_with(|| {
+                let duration = samples.len() as f32 / sampling_rate.0 as f32;
+                now.checked_sub(Duration::from_secs_f32(duration))
+                    .unwrap_or(now)
+            });
+            if paused.load(Ordering::Relaxed) {
+                // keep the timing correct for when the detection is resumed
+                if !samples.is_empty() {
+                    detector.state().update_time(samples.len());
+                }
+                return;
+            }
+            if let Some(info) = detector.is_beat(&samples) {
+                on_beat_cb(info, beat_instant(audio_begin, &info, sampling_rate.0));
+            }
+            if let Some((silence_ms, on_silence_cb)) = &silence {
+                let is_silent = detector.is_silent(*silence_ms);
+                if is_silent != was_silent {
+                    was_silent = is_silent;
+                    on_silence_cb(is_silent);
+                }
+            }
+            let millis = now.elapsed().as_millis();
+            if millis > 20 {
+                eprintln!("calculation took {}ms", millis);
+            }
+        };
+
+        let stream =
+            build_i16_input_stream(&in_dev, &in_stream_cfg, sample_format, on_new_audio).unwrap();
+
+        // start input stream
+        stream.play().unwrap();
+
+        // start listening loop until stopped
+        loop {
+            if !keep_recording.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+    });
+
+    Ok(handle)
+}
+
+/// Like [`start_listening_with_options`] but pushes the beats into a bounded
+/// queue instead of calling a callback.
+///
+/// The beats are taken from the returned [`BeatConsumer`] on any thread, e.g.
+/// once per frame in a GUI. The callback of [`start_listening`] runs inside the
+/// audio callback, where blocking (locks, allocations, I/O) delays the audio
+/// driver and causes glitches. Pushing into the queue never blocks. If the
+/// consumer doesn't keep up and the queue already holds `capacity` beats, new
+/// beats are dropped.
+pub fn start_listening_queued(
+    options: ListenOptions,
+    capacity: usize,
+) -> Result<(JoinHandle<()>, BeatConsumer), String> {
+    let (producer, consumer) = beat_queue(capacity);
+    let handle = start_listening_with_options(options, move |info, _instant| {
+        // the queue is full; dropping the beat is better than blocking audio
+        let _ = producer.push(info);
+    })?;
+    Ok((handle, consumer))
+}
+
+/// Like [`start_listening_with_options`] but writes each beat as one line of
+/// JSON into `writer`, e.g. [`std::io::stdout`], to make the detection scriptable.
+///
+/// The lines are written with [`export::write_json_line`], including the wall
+/// clock time of the beat and the tempo of a [`TempoTracker`] that is fed with
+/// all beats. Write errors are printed to stderr. Writing to stdout or a pipe
+/// may block, so the beats are passed from the audio callback to a separate
+/// writer thread via a bounded channel; if the writer doesn't keep up, new beats
+/// are dropped. The returned handle is the one of the writer thread. It writes
+/// the remaining beats and finishes after `keep_recording` was set to `false`.
+pub fn start_listening_jsonl(
+    writer: impl Write + Send + 'static,
+    options: ListenOptions,
+) -> Result<JoinHandle<()>, String> {
+    let keep_recording = options.keep_recording.clone();
+    let (sender, beats) = sync_channel(JSONL_QUEUE_LEN);
+    let listen_handle = start_listening_with_options(options, move |info, instant| {
+        // never block the audio thread
+        let _ = sender.try_send((info, instant));
+    })?;
+    Ok(spawn(move || {
+        let mut writer = writer;
+        let mut tracker = TempoTracker::new();
+        let mut write_beat = |(info, instant): (BeatInfo, Instant)| {
+            tracker.on_beat(&info);
+            let unix_ms = instant_to_unix_ms(instant);
+            if let Err(err) = export::write_json_line(&info, unix_ms, tracker.bpm(), &mut writer) {
+                eprintln!("Can't write beat: {:?}", err);
+            }
+        };
+        while let Some(beat) = recv_block(&beats, &keep_recording) {
+            write_beat(beat);
+        }
+        let _ = listen_handle.join();
+        beats.try_iter().for_each(write_beat);
+    }))
+}
+
+/// Converts an instant to the milliseconds since the Unix epoch of the system
+/// clock. `None` if the system clock is before the epoch.
+fn instant_to_unix_ms(instant: Instant) -> Option<u64> {
+    let now = Instant::now();
+    let system_now = SystemTime::now();
+    let system_time = if instant <= now {
+        system_now.checked_sub(now - instant)?
+    } else {
+        system_now.checked_add(instant - now)?
+    };
+    let since_epoch = system_time.duration_since(UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_millis() as u64)
+}
+
+/// Feeds a WAV file, e.g. one recorded with [`ListenOptions::with_wav_recording`],
+/// through a detector like in live mode and executes the callback on each beat.
+///
+/// If there is a file with the chunk lengths of the recording (see
+/// [`chunk_lens_path`]), the audio is analyzed in the same chunks as in live
+/// mode, which reproduces the results exactly. Otherwise, the audio is analyzed
+/// in chunks of 1024 samples, which can lead to slightly different results.
+#[cfg(feature = "wav")]
+pub fn replay_wav(
+    wav_path: impl AsRef<Path>,
+    strategy: StrategyKind,
+    on_beat_cb: impl FnMut(BeatInfo),
+) -> Result<(), String> {
+    let wav_path = wav_path.as_ref();
+    let file = File::open(wav_path).map_err(|err| format!("Can't open WAV file: {:?}", err))?;
+    let mut analyzer = StreamAnalyzer::from_wav(BufReader::new(file), strategy)?;
+    if let Some(chunk_lens) = read_chunk_lens(&chunk_lens_path(wav_path))? {
+        analyzer = analyzer.with_block_lens(chunk_lens);
+    }
+    analyzer.by_ref().for_each(on_beat_cb);
+    analyzer
+        .take_error()
+        .map_or(Ok(()), |err| Err(format!("Can't read WAV file: {:?}", err)))
+}
+
+/// Path of the file with the chunk lengths of a WAV recording.
+///
+/// It is the path of the WAV file with the additional extension `.chunks`, e.g.
+/// `rec.wav.chunks`. The file contains the length of each chunk in samples, one
+/// per line. See [`ListenOptions::with_wav_recording`].
+#[cfg(feature = "wav")]
+pub fn chunk_lens_path(wav_path: impl AsRef<Path>) -> PathBuf {
+    let mut path = wav_path.as_ref().as_os_str().to_owned();
+    path.push(".chunks");
+    PathBuf::from(path)
+}
+
+/// Reads the chunk lengths written by a [`WavRecording`]. `None` if the file
+/// doesn't exist.
+#[cfg(feature = "wav")]
+fn read_chunk_lens(path: &Path) -> Result<Option<Vec<usize>>, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("Can't open chunk lengths: {:?}", err)),
+    };
+    BufReader::new(file)
+        .lines()
+        .map(|line| {
+            let line = line.map_err(|err| format!("Can't read chunk lengths: {:?}", err))?;
+            line.trim()
+                .parse()
+                .map_err(|err| format!("Invalid chunk length {:?}: {:?}", line, err))
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// Audio recording of [`ListenOptions::with_wav_recording`]: the WAV file and
+/// the lengths of the chunks.
+#[cfg(feature = "wav")]
+#[derive(Debug)]
+struct WavRecording {
+    wav_writer: WavWriter<BufWriter<File>>,
+    chunk_lens: BufWriter<File>,
+}
+
+#[cfg(feature = "wav")]
+impl WavRecording {
+    /// Creates both files.
+    fn create(wav_path: &Path, sampling_rate: u32) -> Result<Self, String> {
+        let wav_file =
+            File::create(wav_path).map_err(|err| format!("Can't create WAV file: {:?}", err))?;
+        let wav_writer = WavWriter::new(BufWriter::new(wav_file), sampling_rate)
+            .map_err(|err| format!("Can't write WAV file: {:?}", err))?;
+        let chunk_lens = File::create(chunk_lens_path(wav_path))
+            .map_err(|err| format!("Can't create chunk lengths: {:?}", err))?;
+        Ok(Self {
+            wav_writer,
+            chunk_lens: BufWriter::new(chunk_lens),
+        })
+    }
+
+    /// Appends one chunk of mono samples.
+    fn write_chunk(&mut self, samples: &[i16]) -> std::io::Result<()> {
+        self.wav_writer.write_samples(samples)?;
+        writeln!(self.chunk_lens, "{}", samples.len())
+    }
+}
+
+/// Wall clock time of a beat given the wall clock time of the first sample.
+///
+/// The offset is derived from the sample index of the beat and not from its time
+/// in ms, which accumulates rounding errors and is moved by a time origin.
+fn beat_instant(audio_begin: Instant, info: &BeatInfo, sampling_rate: u32) -> Instant {
+    audio_begin + Duration::from_secs_f64(info.sample_index() as f64 / sampling_rate as f64)
+}
+
+/// Capacity of the beat channel of [`start_listening_jsonl`].
+const JSONL_QUEUE_LEN: usize = 64;
+
+/// Maximum amount of audio blocks a [`CpalSource`] buffers for a slow consumer.
+/// 64 blocks of 1024 samples are about 1.5s at 44.1kHz.
+const MAX_PENDING_BLOCKS: usize = 64;
+
+/// How often a waiting [`CpalSource`] checks whether it should stop.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// [`AudioSource`] that records from an audio input device via `cpal`. Use it
+/// with [`crate::io::listen`]. The audio is downmixed to mono.
+///
+/// The audio is recorded as long as this object lives. The source is exhausted,
+/// i.e. [`crate::io::listen`] returns, once `keep_recording` is set to `false`.
+/// Each audio callback allocates one block that is sent to the consuming thread.
+/// At most 64 blocks are buffered; if the consumer is slower than real time,
+/// newer blocks are dropped, see [`CpalSource::dropped_blocks`].
+pub struct CpalSource {
+    // must be kept alive; recording stops when it is dropped
+    _stream: Stream,
+    receiver: Receiver<Vec<i16>>,
+    block: Vec<i16>,
+    sampling_rate: u32,
+    keep_recording: Arc<AtomicBool>,
+    dropped_blocks: Arc<AtomicUsize>,
+}
+
+impl CpalSource {
+    /// Opens the input device (the default device if `input_dev` is `None`) and
+    /// starts recording until `keep_recording` is set to `false`.
+    pub fn new(input_dev: Option<Device>, keep_recording: Arc<AtomicBool>) -> Result<Self, String> {
+        let (in_dev, in_stream_cfg, sample_format) = open_input_device(input_dev)?;
+        let channels = in_stream_cfg.channels;
+        let (sender, receiver) = sync_channel(MAX_PENDING_BLOCKS);
+        let dropped_blocks = Arc::new(AtomicUsize::new(0));
+        let dropped = dropped_blocks.clone();
+        let stream = build_i16_input_stream(&in_dev, &in_stream_cfg, sample_format, move |data| {
+            // never block the audio thread; the receiver is gone if the source was dropped
+            if let Err(TrySendError::Full(_)) = sender.try_send(downmix_interleaved(data, channels))
+            {
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        })?;
+        stream
+            .play()
+            .map_err(|err| format!("Can't start stream: {:?}", err))?;
+        Ok(Self {
+            _stream: stream,
+            receiver,
+            block: Vec::new(),
+            sampling_rate: in_stream_cfg.sample_rate.0,
+            keep_recording,
+            dropped_blocks,
+        })
+    }
+
+    /// Amount of audio blocks that were dropped because the consumer was too
+    /// slow. The time of the detector doesn't advance for dropped blocks.
+    pub fn dropped_blocks(&self) -> usize {
+        self.dropped_blocks.load(Ordering::Relaxed)
+    }
+}
+
+impl AudioSource for CpalSource {
+    fn next_block(&mut self) -> Option<&[i16]> {
+        self.block = recv_block(&self.receiver, &self.keep_recording)?;
+        Some(&self.block)
+    }
+
+    fn sampling_rate(&self) -> u32 {
+        self.sampling_rate
+    }
+}
+
+impl Debug for CpalSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CpalSource")
+            .field("sampling_rate", &self.sampling_rate)
+            .finish()
+    }
+}
+
+/// Waits for the next block of audio data or the next beat. Returns `None` once
+/// `keep_recording` is `false` or the sender is gone.
+fn recv_block<T>(receiver: &Receiver<T>, keep_recording: &AtomicBool) -> Option<T> {
+    while keep_recording.load(Ordering::SeqCst) {
+        match receiver.recv_timeout(STOP_POLL_INTERVAL) {
+            Ok(block) => return Some(block),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+    None
+}
+
+/// Selects the input device (the default device if `input_dev` is `None`) and
+/// returns it together with a validated stream config and its sample format.
+fn open_input_device(
+    input_dev: Option<Device>,
+) -> Result<(Device, StreamConfig, SampleFormat), String> {
     // we either use the "cpal" audio device the user wants to use
     // or otherwise the default input device
     let in_dev = input_dev.map(Ok).unwrap_or_else(|| {
@@ -53,25 +582,28 @@ pub fn start_listening(
         host.default_input_device()
             .ok_or_else(|| "Must have input device!".to_string())
     })?;
-    let in_dev_cfg = in_dev.default_input_config().unwrap();
-    // let channels = in_dev_cfg.channels();
+    let in_dev_cfg = in_dev
+        .default_input_config()
+        .map_err(|err| format!("Can't get default input config: {:?}", err))?;
+    // pro audio interfaces and loopback devices often expose more than two
+    // channels; we open the device with its native channel count and downmix
+    let channels = in_dev_cfg.channels();
+    if channels == 0 {
+        return Err("Input device reports zero channels!".to_string());
+    }
     let sampling_rate = in_dev_cfg.sample_rate();
     let sample_format = in_dev_cfg.sample_format();
     eprintln!("Using input device: {:?}", in_dev.name().unwrap());
-    // eprintln!("  channels: {}", channels);
+    eprintln!("  channels: {}", channels);
     eprintln!("  sampling_rate: {}", sampling_rate.0);
     eprintln!("  sample_format: {:?}", sample_format);
 
-    let err_cb = |err: StreamError| {
-        eprintln!("Record error occurred: {:#?}", err);
-    };
-
     // 1/44100 * 1024 = 23.22ms
     #[cfg(not(target_os = "linux"))]
     let preferred_window_length = 1024;
 
     let in_stream_cfg = StreamConfig {
-        channels: 1,
+        channels,
         sample_rate: sampling_rate,
         #[cfg(not(target_os = "linux"))]
         buffer_size: BufferSize::Fixed(preferred_window_length),
@@ -82,87 +614,234 @@ pub fn start_listening(
         buffer_size: BufferSize::Default,
     };
 
-    let detector = strategy.detector(sampling_rate.0);
+    // fail early with a meaningful message instead of a cryptic error
+    // when the stream is built
+    validate_stream_config(&in_dev, &in_stream_cfg, sample_format)?;
 
-    let handle = spawn(move || {
-        // abstraction over possible return types
-        // map all to [i16] and then do the appropriate callback
-        let stream = match sample_format {
-            SampleFormat::F32 => in_dev.build_input_stream(
-                &in_stream_cfg,
-                move |data: &[f32], _info: &InputCallbackInfo| {
-                    let now = Instant::now();
-                    if let Some(info) = detector.is_beat(&f32_data_to_i16(data)) {
-                        on_beat_cb(info);
-                    }
-                    let millis = now.elapsed().as_millis();
-                    if millis > 20 {
-                        eprintln!("calculation took {}ms", millis);
-                    }
-                },
-                err_cb,
-            ),
-            SampleFormat::I16 => in_dev.build_input_stream(
-                &in_stream_cfg,
-                move |data: &[i16], _info: &InputCallbackInfo| {
-                    let now = Instant::now();
-                    if let Some(info) = detector.is_beat(data) {
-                        on_beat_cb(info);
-                    }
-                    let millis = now.elapsed().as_millis();
-                    if millis > 20 {
-                        eprintln!("calculation took {}ms", millis);
-                    }
-                },
-                err_cb,
-            ),
-            SampleFormat::U16 => in_dev.build_input_stream(
-                &in_stream_cfg,
-                move |data: &[u16], _info: &InputCallbackInfo| {
-                    let now = Instant::now();
-                    if let Some(info) = detector.is_beat(&u16_data_to_i16(data)) {
-                        on_beat_cb(info);
-                    }
-                    let millis = now.elapsed().as_millis();
-                    if millis > 20 {
-                        eprintln!("calculation took {}ms", millis);
-                    }
-                },
-                err_cb,
-            ),
-        }
-        .map_err(|err| format!("Can't open stream: {:?}", err))
-        .unwrap();
+    Ok((in_dev, in_stream_cfg, sample_format))
+}
 
-        // start input stream
-        stream.play().unwrap();
+/// Builds an input stream that maps all sample formats to interleaved `i16`
+/// audio data and passes it to `on_data`. The stream isn't started yet.
+fn build_i16_input_stream(
+    in_dev: &Device,
+    in_stream_cfg: &StreamConfig,
+    sample_format: SampleFormat,
+    mut on_data: impl FnMut(&[i16]) + Send + 'static,
+) -> Result<Stream, String> {
+    let err_cb = |err: StreamError| {
+        eprintln!("Record error occurred: {:#?}", err);
+    };
+    // reused for each callback to prevent allocations in the audio thread
+    let mut converted = Vec::new();
 
-        // start listening loop until stopped
-        loop {
-            if !keep_recording.load(Ordering::SeqCst) {
-                break;
-            }
+    // abstraction over possible return types
+    // map all to [i16] and then do the appropriate callback
+    match sample_format {
+        SampleFormat::F32 => in_dev.build_input_stream(
+            in_stream_cfg,
+            move |data: &[f32], _info: &InputCallbackInfo| {
+                converted.clear();
+                converted.extend(data.iter().map(|x| f32_sample_to_i16(*x)));
+                on_data(&converted)
+            },
+            err_cb,
+        ),
+        SampleFormat::I16 => in_dev.build_input_stream(
+            in_stream_cfg,
+            move |data: &[i16], _info: &InputCallbackInfo| on_data(data),
+            err_cb,
+        ),
+        SampleFormat::U16 => in_dev.build_input_stream(
+            in_stream_cfg,
+            move |data: &[u16], _info: &InputCallbackInfo| {
+                converted.clear();
+                converted.extend(data.iter().map(|x| u16_sample_to_i16(*x)));
+                on_data(&converted)
+            },
+            err_cb,
+        ),
+    }
+    .map_err(|err| format!("Can't open stream: {:?}", err))
+}
+
+/// Checks if the device supports the stream config with the given sample format.
+/// The channels, sampling rate and sample format come from the default config of
+/// the device, but the fixed buffer size is chosen by us.
+fn validate_stream_config(
+    dev: &Device,
+    cfg: &StreamConfig,
+    sample_format: SampleFormat,
+) -> Result<(), String> {
+    let is_supported = dev
+        .supported_input_configs()
+        .map_err(|err| format!("Can't get supported input configs: {:?}", err))?
+        .any(|range| {
+            range.channels() == cfg.channels
+                && range.sample_format() == sample_format
+                && range.min_sample_rate() <= cfg.sample_rate
+                && cfg.sample_rate <= range.max_sample_rate()
+                && supports_buffer_size(range.buffer_size(), &cfg.buffer_size)
+        });
+    if is_supported {
+        Ok(())
+    } else {
+        Err(format!(
+            "Input device doesn't support config {:?} with sample format {:?}",
+            cfg, sample_format
+        ))
+    }
+}
+
+/// Checks if the requested buffer size is in the supported range. Devices that
+/// can't tell their range before the stream is built accept every size here.
+fn supports_buffer_size(supported: &SupportedBufferSize, requested: &BufferSize) -> bool {
+    match (supported, requested) {
+        (SupportedBufferSize::Range { min, max }, BufferSize::Fixed(size)) => {
+            min <= size && size <= max
         }
-    });
+        _ => true,
+    }
+}
 
-    Ok(handle)
+/// Analyzes interleaved `i16` audio data with `channels` channels.
+///
+/// It works the same way [`start_listening`] does it with the data of the audio
+/// device: the data is downmixed to mono and passed to the detector. Useful if you build your own
+/// input stream, e.g. with a custom `cpal` config, or already have PCM data.
+#[inline(always)]
+pub fn process_i16(detector: &dyn Strategy, samples: &[i16], channels: u16) -> Option<BeatInfo> {
+    detector.is_beat_interleaved(samples, channels)
 }
 
+/// Downmixes interleaved audio data with an arbitrary number of channels
+/// to mono by taking the mean of all samples of each frame. A trailing
+/// incomplete frame is ignored.
+#[inline(always)]
+pub fn downmix_interleaved(data: &[i16], channels: u16) -> Vec<i16> {
+    let mut mono = Vec::with_capacity(data.len() / channels.max(1) as usize);
+    downmix_interleaved_into(data, channels, &mut mono);
+    mono
+}
+
+/// Like [`downmix_interleaved`] but writes into `out`, which is cleared first.
+///
+/// Reusing the same buffer for each callback prevents allocations in the
+/// audio thread as soon as the buffer has the required capacity.
+#[inline(always)]
+pub fn downmix_interleaved_into(data: &[i16], channels: u16, out: &mut Vec<i16>) {
+    out.clear();
+    if channels <= 1 {
+        out.extend_from_slice(data);
+        return;
+    }
+    out.extend(
+        data.chunks_exact(channels as usize)
+            .map(|frame| frame.iter().map(|x| *x as i32).sum::<i32>() / channels as i32)
+            .map(|x| x as i16),
+    );
+}
+
+/// Converts unsigned 16 bit audio data to signed audio data by moving
+/// the zero line from the midpoint of the value range to `0`.
 #[inline(always)]
 pub fn u16_data_to_i16(data: &[u16]) -> Vec<i16> {
-    data.iter()
-        .map(|x| *x as i32)
-        .map(|x| x - i16::MAX as i32 / 2)
-        .map(|x| x as i16)
-        .collect()
+    data.iter().map(|x| u16_sample_to_i16(*x)).collect()
+}
+
+/// Converts a single sample, see [`u16_data_to_i16`].
+#[inline(always)]
+pub const fn u16_sample_to_i16(sample: u16) -> i16 {
+    (sample as i32 - (u16::MAX as i32 + 1) / 2) as i16
 }
 
+/// Scales audio data in range `[-1, 1]` to `i16`.
+///
+/// `-1.0` maps to `-i16::MAX`, i.e. `i16::MIN` is never produced. Real device audio
+/// occasionally exceeds unity by a hair, therefore values outside of the range
+/// are clamped instead of rejected.
 #[inline(always)]
 pub fn f32_data_to_i16(data: &[f32]) -> Vec<i16> {
-    data.iter()
-        .map(|x| x * i16::MAX as f32)
-        .map(|x| x as i16)
-        .collect()
+    data.iter().map(|x| f32_sample_to_i16(*x)).collect()
+}
+
+/// Converts a single sample, see [`f32_data_to_i16`].
+#[inline(always)]
+pub fn f32_sample_to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Converts an unsigned 8 bit sample to range `[-1, 1]`. Unsigned PCM has its
+/// zero line at the midpoint of the value range, which is subtracted first.
+#[inline(always)]
+pub fn u8_sample_to_f32_sample(val: u8) -> f32 {
+    const MID: f32 = 128.0;
+    (val as f32 - MID) / MID
+}
+
+/// Converts an unsigned 16 bit sample to range `[-1, 1]`. Unsigned PCM has its
+/// zero line at the midpoint of the value range, which is subtracted first.
+#[inline(always)]
+pub fn u16_sample_to_f32_sample(val: u16) -> f32 {
+    const MID: f32 = 32768.0;
+    (val as f32 - MID) / MID
+}
+
+/// Sends each beat as OSC message to a target, e.g. lighting or VJ software.
+///
+/// The message has the address `/beat` and the arguments intensity (float,
+/// `[0, 1]`) and the relative time of the beat in ms (int). It is sent via UDP,
+/// so it can be used directly in the callback of [`start_listening`]:
+///
+/// ```no_run
+/// use beat_detector::record::BeatOscSender;
+/// let osc = BeatOscSender::new("127.0.0.1:9000".parse().unwrap()).unwrap();
+/// let on_beat = move |info| {
+///     let _ = osc.send(&info);
+/// };
+/// ```
+#[derive(Debug)]
+pub struct BeatOscSender {
+    socket: UdpSocket,
+    target: SocketAddr,
+}
+
+impl BeatOscSender {
+    /// Constructor for [`BeatOscSender`]. Binds a UDP socket on an arbitrary port.
+    pub fn new(target: SocketAddr) -> std::io::Result<Self> {
+        let bind_addr = if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        Ok(Self {
+            socket: UdpSocket::bind(bind_addr)?,
+            target,
+        })
+    }
+
+    /// Sends the beat as OSC message to the target.
+    pub fn send(&self, info: &BeatInfo) -> std::io::Result<()> {
+        self.socket
+            .send_to(&encode_osc_beat(info), self.target)
+            .map(|_| ())
+    }
+}
+
+/// Encodes a beat as OSC message `/beat ,fi <intensity> <relative_ms>`.
+fn encode_osc_beat(info: &BeatInfo) -> Vec<u8> {
+    // OSC strings are null terminated and padded to a multiple of 4 bytes
+    fn push_osc_str(msg: &mut Vec<u8>, str: &str) {
+        msg.extend_from_slice(str.as_bytes());
+        msg.resize(msg.len() + 4 - str.len() % 4, 0);
+    }
+
+    let mut msg = Vec::with_capacity(20);
+    push_osc_str(&mut msg, "/beat");
+    push_osc_str(&mut msg, ",fi");
+    msg.extend_from_slice(&info.intensity().to_be_bytes());
+    msg.extend_from_slice(&(info.relative_ms() as i32).to_be_bytes());
+    msg
 }
 
 /// Convenient function which helps you to select from a number of
@@ -200,4 +879,184 @@ pub fn get_backends() -> HashMap<String, Host> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::synth_beats;
+
+    #[test]
+    fn test_downmix_interleaved() {
+        assert_eq!(vec![1, 2, 3], downmix_interleaved(&[1, 2, 3], 1));
+        assert_eq!(vec![15, -5], downmix_interleaved(&[10, 20, -10, 0], 2));
+        // 6 channels, e.g. a 5.1 loopback device
+        assert_eq!(
+            vec![3, 0],
+            downmix_interleaved(&[1, 2, 3, 4, 5, 3, 0, 0, 0, 0, 0, 0], 6)
+        );
+        // incomplete trailing frame is dropped
+        assert_eq!(vec![2], downmix_interleaved(&[1, 2, 3, 4, 5], 4));
+    }
+
+    #[test]
+    fn test_downmix_interleaved_into_reuses_buffer() {
+        let mut out = Vec::with_capacity(4);
+        downmix_interleaved_into(&[10, 20, -10, 0], 2, &mut out);
+        assert_eq!(vec![15, -5], out);
+        let ptr = out.as_ptr();
+        downmix_interleaved_into(&[1, 1, 2, 2, 3, 3, 4, 4], 2, &mut out);
+        assert_eq!(vec![1, 2, 3, 4], out);
+        assert_eq!(ptr, out.as_ptr(), "must not reallocate");
+    }
+
+    #[test]
+    fn test_recv_block() {
+        let keep_recording = AtomicBool::new(true);
+        let (sender, receiver) = sync_channel(2);
+        sender.send(vec![1, 2]).unwrap();
+        assert_eq!(Some(vec![1, 2]), recv_block(&receiver, &keep_recording));
+
+        // stops while waiting for data
+        let stop = Arc::new(AtomicBool::new(true));
+        let stop_later = stop.clone();
+        let handle = spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            stop_later.store(false, Ordering::SeqCst);
+        });
+        assert_eq!(None, recv_block(&receiver, &stop));
+        handle.join().unwrap();
+
+        // stops when the sender is gone
+        drop(sender);
+        assert_eq!(None, recv_block(&receiver, &keep_recording));
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn test_wav_recording_replay() {
+        let wav_path =
+            std::env::temp_dir().join(format!("beat-detector-test-{}.wav", std::process::id()));
+        let samples = synth_beats(44100, 120.0, 8, 50.0);
+        // chunk sizes as some audio drivers deliver them
+        let chunks = [480, 530, 512, 7].iter().cycle().scan(0, |begin, len| {
+            let chunk = samples.get(*begin..(*begin + len).min(samples.len()))?;
+            *begin += len;
+            Some(chunk).filter(|chunk| !chunk.is_empty())
+        });
+        let detector = StrategyKind::LPF.detector(44100);
+        let mut recording = WavRecording::create(&wav_path, 44100).unwrap();
+        let mut live = Vec::new();
+        for chunk in chunks {
+            recording.write_chunk(chunk).unwrap();
+            live.extend(detector.is_beat(chunk).map(|info| info.sample_index()));
+        }
+        drop(recording);
+
+        let mut replayed = Vec::new();
+        replay_wav(&wav_path, StrategyKind::LPF, |info| {
+            replayed.push(info.sample_index())
+        })
+        .unwrap();
+        std::fs::remove_file(&wav_path).unwrap();
+        std::fs::remove_file(chunk_lens_path(&wav_path)).unwrap();
+        assert_eq!(8, live.len());
+        assert_eq!(live, replayed);
+    }
+
+    #[test]
+    fn test_instant_to_unix_ms() {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let unix_ms = instant_to_unix_ms(Instant::now() - Duration::from_secs(1)).unwrap();
+        assert!((now_ms - 1100..=now_ms - 900).contains(&unix_ms));
+        let unix_ms = instant_to_unix_ms(Instant::now() + Duration::from_secs(1)).unwrap();
+        assert!((now_ms + 900..=now_ms + 1100).contains(&unix_ms));
+    }
+
+    #[test]
+    fn test_beat_instant() {
+        let audio_begin = Instant::now();
+        // 10 minutes at 44.1kHz
+        let info = BeatInfo::new(0, 1.0).with_sample_index(600 * 44100);
+        let instant = beat_instant(audio_begin, &info, 44100);
+        assert_eq!(Duration::from_secs(600), instant - audio_begin);
+    }
+
+    #[test]
+    fn test_supports_buffer_size() {
+        let range = SupportedBufferSize::Range { min: 64, max: 512 };
+        assert!(supports_buffer_size(&range, &BufferSize::Fixed(512)));
+        assert!(!supports_buffer_size(&range, &BufferSize::Fixed(1024)));
+        assert!(supports_buffer_size(&range, &BufferSize::Default));
+        assert!(supports_buffer_size(
+            &SupportedBufferSize::Unknown,
+            &BufferSize::Fixed(1024)
+        ));
+    }
+
+    #[test]
+    fn test_process_i16() {
+        let stereo = synth_beats(44100, 120.0, 4, 50.0)
+            .iter()
+            .flat_map(|x| vec![*x, *x])
+            .collect::<Vec<_>>();
+        let detector = StrategyKind::LPF.detector(44100);
+        let beats = stereo
+            .chunks(2 * 1024)
+            .filter_map(|chunk| process_i16(detector.as_ref(), chunk, 2))
+            .count();
+        assert_eq!(4, beats);
+        // mono time base: 4 hits + 1 period of silence at 120 BPM
+        assert_eq!(5 * 22050, detector.samples_processed());
+    }
+
+    #[test]
+    fn test_encode_osc_beat() {
+        let msg = encode_osc_beat(&BeatInfo::new(1000, 0.5));
+        let mut expected = b"/beat\0\0\0,fi\0".to_vec();
+        expected.extend_from_slice(&0.5_f32.to_be_bytes());
+        expected.extend_from_slice(&[0, 0, 0x03, 0xE8]);
+        assert_eq!(expected, msg);
+    }
+
+    #[test]
+    fn test_beat_osc_sender() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let sender = BeatOscSender::new(receiver.local_addr().unwrap()).unwrap();
+        let info = BeatInfo::new(42, 1.0);
+        sender.send(&info).unwrap();
+
+        let mut buf = [0; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(encode_osc_beat(&info), &buf[..len]);
+    }
+
+    #[test]
+    fn test_u16_data_to_i16() {
+        assert_eq!(
+            vec![i16::MIN, 0, i16::MAX],
+            u16_data_to_i16(&[0, 32768, u16::MAX])
+        );
+    }
+
+    #[test]
+    fn test_f32_data_to_i16() {
+        assert_eq!(
+            vec![0, i16::MAX, -i16::MAX, i16::MAX / 2],
+            f32_data_to_i16(&[0.0, 1.0, -1.0, 0.5])
+        );
+        // out of range
+        assert_eq!(vec![i16::MAX, -i16::MAX], f32_data_to_i16(&[1.5, -1.5]));
+    }
+
+    #[test]
+    fn test_unsigned_sample_to_f32_sample() {
+        assert_eq!(0.0, u8_sample_to_f32_sample(128));
+        assert_eq!(-1.0, u8_sample_to_f32_sample(0));
+        assert!(u8_sample_to_f32_sample(255) > 0.99);
+        assert_eq!(0.0, u16_sample_to_f32_sample(32768));
+        assert_eq!(-1.0, u16_sample_to_f32_sample(0));
+        assert!(u16_sample_to_f32_sample(u16::MAX) > 0.9999);
+    }
 }
@@ -23,15 +23,18 @@ SOFTWARE.
 */
 use crate::strategies::window_stats::WindowStats;
 use crate::strategies::AnalysisState;
-use crate::{BeatInfo, Strategy, StrategyKind};
+use crate::{AnalysisOutcome, BeatInfo, LastAnalysis, Strategy, StrategyKind};
 use lowpass_filter as lpf;
 
+/// Cutoff frequency of the lowpass filter in Hz.
+pub(crate) const CUTOFF_FR: u16 = 120;
+
 /// Struct to provide a beat-detection strategy using a
 /// lowpass filter.The algorithm is pretty basic/stupid.
 /// It's not smart enough to cope with 'complex' music, like
 /// most of today's pop. But it will give pretty good results
 /// in 'easy' music, like most of 90s pop hits.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LpfBeatDetector {
     state: AnalysisState,
 }
@@ -43,6 +46,15 @@ impl LpfBeatDetector {
             state: AnalysisState::new(sampling_rate),
         }
     }
+
+    /// Constructor for a detector whose sampling rate is set later
+    /// via [`Strategy::set_sampling_rate`].
+    #[inline(always)]
+    pub const fn uninitialized() -> Self {
+        Self {
+            state: AnalysisState::uninitialized(),
+        }
+    }
 }
 
 impl Strategy for LpfBeatDetector {
@@ -50,19 +62,32 @@ impl Strategy for LpfBeatDetector {
     /// applying a lowpass filter onto the data.
     #[inline(always)]
     fn is_beat(&self, samples: &[i16]) -> Option<BeatInfo> {
+        // nothing to analyze; some audio drivers deliver empty callbacks
+        if samples.is_empty() {
+            return None;
+        }
         // tell the state beforehand that we are analyzing the next window - important!
         self.state.update_time(samples.len());
+        self.state.update_level(samples);
         // skip if distance to last beat is not fair away enough
         if !self.last_beat_beyond_threshold(&self.state) {
+            self.state.set_last_analysis(LastAnalysis {
+                outcome: AnalysisOutcome::TooCloseToLastBeat,
+                ..LastAnalysis::NOT_ANALYZED
+            });
             return None;
         };
         // skip if the amplitude is too low, e.g. noise or silence between songs
         let w_stats = WindowStats::from(samples);
         if !self.amplitude_high_enough(&w_stats) {
+            self.state.set_last_analysis(LastAnalysis {
+                outcome: AnalysisOutcome::AmplitudeTooLow,
+                intensity: Some(w_stats.intensity()),
+                ..LastAnalysis::NOT_ANALYZED
+            });
             return None;
         };
 
-        const CUTOFF_FR: u16 = 120;
         let mut samples = samples.to_vec();
         lpf::simple::sp::apply_lpf_i16_sp(
             &mut samples,
@@ -72,12 +97,25 @@ impl Strategy for LpfBeatDetector {
         // check if after the low pass filter we still have high amplitude
         // => then this is dominant in the window
         let threshold = (0.77 * w_stats.max() as f32) as i16;
-        let is_beat = samples.iter().any(|s| s.abs() >= threshold);
+        let filtered_max = samples.iter().map(|s| (*s as i32).abs()).max().unwrap_or(0);
+        let is_beat = filtered_max >= threshold as i32;
+        self.state.set_last_analysis(LastAnalysis {
+            outcome: if is_beat {
+                AnalysisOutcome::Beat
+            } else {
+                AnalysisOutcome::BelowCriteria
+            },
+            intensity: Some(w_stats.intensity()),
+            criterion: Some(filtered_max as f32),
+            threshold: Some(threshold as f32),
+        });
 
         is_beat.then(|| {
             // mark we found a beat
             self.state.update_last_discovered_beat_timestamp();
-            BeatInfo::new(self.state.beat_time_ms())
+            BeatInfo::new(self.state.reported_beat_time_ms(), w_stats.intensity())
+                .with_brightness(w_stats.brightness())
+                .with_sample_index(self.state.beat_sample_index())
         })
     }
 
@@ -86,6 +124,20 @@ impl Strategy for LpfBeatDetector {
         StrategyKind::LPF
     }
 
+    #[inline(always)]
+    fn state(&self) -> &AnalysisState {
+        &self.state
+    }
+
+    #[inline(always)]
+    fn set_sampling_rate(&mut self, sampling_rate: u32) {
+        self.state.set_sampling_rate(sampling_rate)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Strategy + Send> {
+        Box::new(self.clone())
+    }
+
     fn name() -> &'static str
     where
         Self: Sized,
@@ -21,25 +21,35 @@ LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
+use crate::LastAnalysis;
 use std::cell::Cell;
 
+/// Half-life in ms of the recent maximum amplitude that [`AnalysisState::beat_activation`]
+/// is relative to.
+const ACTIVATION_MAX_HALF_LIFE_MS: f32 = 2000.0;
+/// Half-life in ms of the decay of [`AnalysisState::beat_activation`] between beats.
+const ACTIVATION_RELEASE_HALF_LIFE_MS: f32 = 150.0;
+/// Maximum amplitude in range `[0, 1]` below which a window counts as silent.
+const SILENCE_THRESHOLD: f32 = 0.05;
+
 pub(crate) mod lpf;
 pub(crate) mod spectrum;
 pub mod window_stats;
 
-/// Structure that each [`super::Strategy`]-implementation shall use. It helps to keep
-/// internal state about the ongoing analysis, i.e. the progress in time. It is
-/// capable to work with different window/frame sizes. This is especially required
+/// Structure that each [`super::Strategy`]-implementation shall use.
+///
+/// It helps to keep internal state about the ongoing analysis, i.e. the progress
+/// in time. It is capable to work with different window/frame sizes. This is especially required
 /// for Linux because right now it doesn't work there to use a fixed buffer size -_-
 ///
 /// This struct shall be updated live/on the fly while music is recorded. Therefore,
 /// the time inside this struct is almost the real (relative) time from the beginning
 /// of recording, despite some latency.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AnalysisState {
-    /// Sampling rate of the measurement. This is immutable, i.e it assumes that
-    /// this value doesn't change during ongoing analysis. Value is for example
-    /// 44100 Hz.
+    /// Sampling rate of the measurement. It assumes that this value doesn't change
+    /// during ongoing analysis. Value is for example 44100 Hz. It is `0` as long
+    /// as the state is uninitialized, see [`AnalysisState::uninitialized`].
     sampling_rate: u32,
     /// Calculated once by `1 / ms_per_sample`. Done once to speed up calculation.
     ms_per_sample: f32,
@@ -53,27 +63,89 @@ pub struct AnalysisState {
     /// Timestamp of last beat. This is always a value that was previously in
     /// [`beat_time_ms`].
     last_beat_timestamp: Cell<u32>,
+    /// Total amount of (mono) samples that were analyzed so far.
+    samples_processed: Cell<usize>,
+    /// Amount of samples of the latest frame/window.
+    frame_len: Cell<usize>,
+    /// User-provided timestamp in ms that [`time_origin_set_at_ms`] maps to
+    /// in reported beats. See [`AnalysisState::set_time_origin`].
+    time_origin_ms: Cell<u32>,
+    /// Value of [`time_ms`] when the time origin was set.
+    time_origin_set_at_ms: Cell<u32>,
+    /// Diagnostics about the latest window.
+    last_analysis: Cell<LastAnalysis>,
+    /// Slowly decaying maximum amplitude of the recent windows in range `[0, 1]`.
+    recent_max_amplitude: Cell<f32>,
+    /// See [`AnalysisState::beat_activation`].
+    beat_activation: Cell<f32>,
+    /// Value of [`samples_processed`] at the end of the latest window that
+    /// wasn't silent.
+    last_loud_samples_processed: Cell<usize>,
 }
 
 impl AnalysisState {
     /// Constructor for [`AnalysisState`].
+    /// Panics if `sampling_rate` is `0`.
     pub fn new(sampling_rate: u32) -> Self {
+        let mut state = Self::uninitialized();
+        state.set_sampling_rate(sampling_rate);
+        state
+    }
+
+    /// Constructor for an [`AnalysisState`] whose sampling rate is not known yet,
+    /// e.g. because it only arrives with the first audio callback.
+    /// [`AnalysisState::set_sampling_rate`] must be called before the first
+    /// window is analyzed, otherwise [`AnalysisState::update_time`] panics.
+    pub const fn uninitialized() -> Self {
         Self {
-            sampling_rate,
-            // Hertz => second => milli seconds
-            ms_per_sample: 1.0 / sampling_rate as f32 * 1000.0,
+            sampling_rate: 0,
+            ms_per_sample: 0.0,
             beat_time_ms: Cell::new(0),
             time_ms: Cell::new(0),
             last_beat_timestamp: Cell::new(0),
+            samples_processed: Cell::new(0),
+            frame_len: Cell::new(0),
+            time_origin_ms: Cell::new(0),
+            time_origin_set_at_ms: Cell::new(0),
+            last_analysis: Cell::new(LastAnalysis::NOT_ANALYZED),
+            recent_max_amplitude: Cell::new(0.0),
+            beat_activation: Cell::new(0.0),
+            last_loud_samples_processed: Cell::new(0),
         }
     }
 
+    /// Sets the sampling rate of the measurement. Intended to be called once
+    /// on an uninitialized state before the analysis starts. Panics if
+    /// `sampling_rate` is `0`.
+    #[inline(always)]
+    pub fn set_sampling_rate(&mut self, sampling_rate: u32) {
+        assert!(
+            sampling_rate > 0,
+            "The sampling rate must be greater than 0!"
+        );
+        self.sampling_rate = sampling_rate;
+        // Hertz => second => milli seconds
+        self.ms_per_sample = 1.0 / sampling_rate as f32 * 1000.0;
+    }
+
+    /// Whether the sampling rate is known, i.e. if the analysis can start.
+    #[inline(always)]
+    pub const fn is_initialized(&self) -> bool {
+        self.sampling_rate > 0
+    }
+
     /// Updates the total passed internal time. It does so by calculating the milliseconds of
-    /// the amount of (mono, not stereo!) samples for the given [`sampling_rate`].
+    /// the amount of (mono, not stereo!) samples for the given `sampling_rate`.
     /// It always adds the timestamp in the middle of the current window/frame to
     /// the current value.
+    ///
+    /// Panics if the sampling rate was never set.
     #[inline(always)]
     pub fn update_time(&self, frame_len: usize) {
+        assert!(
+            self.is_initialized(),
+            "The sampling rate must be set before audio data can be analyzed!"
+        );
         // if 44,1kHz is sampling rate and we have 44,1k samples => 1s
         let ms_of_frame = self.ms_per_sample * frame_len as f32;
         self.beat_time_ms.set(
@@ -81,6 +153,9 @@ impl AnalysisState {
             self.time_ms.get() + (ms_of_frame / 2.0) as u32,
         );
         self.time_ms.set(self.time_ms.get() + ms_of_frame as u32);
+        self.samples_processed
+            .set(self.samples_processed.get() + frame_len);
+        self.frame_len.set(frame_len);
     }
 
     /// Updates the timestamp of the last received beat.
@@ -90,29 +165,164 @@ impl AnalysisState {
         self.last_beat_timestamp.replace(self.beat_time_ms.get());
     }
 
-    /// Getter for [`sampling_rate`].
+    /// Getter for `sampling_rate`.
     #[inline(always)]
     pub const fn sampling_rate(&self) -> u32 {
         self.sampling_rate
     }
 
-    /// Getter for [`last_beat_timestamp`].
+    /// Getter for `last_beat_timestamp`.
     #[inline(always)]
     pub fn last_beat_timestamp(&self) -> u32 {
         self.last_beat_timestamp.get()
     }
 
-    /// Getter for [`beat_time_ms`].
+    /// Getter for `beat_time_ms`.
     #[inline(always)]
     pub fn beat_time_ms(&self) -> u32 {
         self.beat_time_ms.get()
     }
 
-    /// Getter for [`time_ms`].
+    /// Timestamp of the current beat as reported to the user, i.e.
+    /// [`AnalysisState::beat_time_ms`] relative to the time origin. See
+    /// [`AnalysisState::set_time_origin`].
+    #[inline(always)]
+    pub fn reported_beat_time_ms(&self) -> u32 {
+        // saturate instead of overflowing for origins close to `u32::MAX`
+        self.time_origin_ms.get().saturating_add(
+            self.beat_time_ms
+                .get()
+                .saturating_sub(self.time_origin_set_at_ms.get()),
+        )
+    }
+
+    /// Index of the sample in the middle of the latest window since the beginning
+    /// of the stream, i.e. the sample that a beat in this window is attributed to.
+    #[inline(always)]
+    pub fn beat_sample_index(&self) -> usize {
+        self.samples_processed.get() - self.frame_len.get() + self.frame_len.get() / 2
+    }
+
+    /// Sets the timestamp in ms that the audio analyzed next starts at. All
+    /// subsequently reported beats are relative to this origin, e.g. the start
+    /// of a new clip in a session that splices several recordings. This only
+    /// affects reported timestamps; the internal time, which is used to skip
+    /// beats that are too close to the previous one, keeps running. There is
+    /// no reset: the origin stays in effect until it is set again.
+    #[inline(always)]
+    pub fn set_time_origin(&self, origin_ms: u32) {
+        self.time_origin_ms.set(origin_ms);
+        self.time_origin_set_at_ms.set(self.time_ms.get());
+    }
+
+    /// Updates [`AnalysisState::beat_activation`] and the silence detection with
+    /// the samples of the current window. Must be called after
+    /// [`AnalysisState::update_time`].
+    #[inline(always)]
+    pub fn update_level(&self, samples: &[i16]) {
+        let amplitude = samples
+            .iter()
+            .map(|x| (*x as i32).abs())
+            .max()
+            .unwrap_or(0)
+            .min(i16::MAX as i32) as f32
+            / i16::MAX as f32;
+        let ms_of_frame = self.ms_per_sample * samples.len() as f32;
+        let max_decay = 0.5_f32.powf(ms_of_frame / ACTIVATION_MAX_HALF_LIFE_MS);
+        let release = 0.5_f32.powf(ms_of_frame / ACTIVATION_RELEASE_HALF_LIFE_MS);
+
+        let recent_max = amplitude.max(self.recent_max_amplitude.get() * max_decay);
+        self.recent_max_amplitude.set(recent_max);
+        let ratio = if recent_max > 0.0 {
+            amplitude / recent_max
+        } else {
+            0.0
+        };
+        self.beat_activation
+            .set(ratio.max(self.beat_activation.get() * release));
+
+        if amplitude >= SILENCE_THRESHOLD {
+            self.last_loud_samples_processed
+                .set(self.samples_processed.get());
+        }
+    }
+
+    /// Whether all audio of at least the last `min_duration_ms` was below the
+    /// noise floor, e.g. because the music stopped.
+    #[inline(always)]
+    pub fn is_silent(&self, min_duration_ms: u32) -> bool {
+        if !self.is_initialized() {
+            return false;
+        }
+        let silent_samples = self.samples_processed.get() - self.last_loud_samples_processed.get();
+        silent_samples as u64 * 1000 / self.sampling_rate as u64 >= min_duration_ms as u64
+    }
+
+    /// Time in ms since the latest beat or `None` if there was no beat yet.
+    #[inline(always)]
+    pub fn time_since_last_beat_ms(&self) -> Option<u32> {
+        // a beat can't be at time 0, see [`crate::Strategy::last_beat_beyond_threshold`]
+        match self.last_beat_timestamp.get() {
+            0 => None,
+            timestamp => Some(self.time_ms.get() - timestamp),
+        }
+    }
+
+    /// Continuous "beatiness" in range `[0, 1]`: the maximum amplitude of the
+    /// latest window relative to the recent maximum. It rises towards `1` around
+    /// a beat and decays smoothly between beats. Useful to drive parameters
+    /// smoothly instead of with hard flashes on each [`crate::BeatInfo`].
+    #[inline(always)]
+    pub fn beat_activation(&self) -> f32 {
+        self.beat_activation.get()
+    }
+
+    /// Getter for `last_analysis`.
+    #[inline(always)]
+    pub fn last_analysis(&self) -> LastAnalysis {
+        self.last_analysis.get()
+    }
+
+    /// Stores the diagnostics of the window that was just analyzed.
+    #[inline(always)]
+    pub fn set_last_analysis(&self, last_analysis: LastAnalysis) {
+        self.last_analysis.set(last_analysis);
+    }
+
+    /// Getter for `time_ms`.
     #[inline(always)]
     pub fn time_ms(&self) -> u32 {
         self.time_ms.get()
     }
+
+    /// Getter for `samples_processed`.
+    #[inline(always)]
+    pub fn samples_processed(&self) -> usize {
+        self.samples_processed.get()
+    }
+
+    /// Upper bound in ms for how far a reported beat lags behind the audio.
+    ///
+    /// A beat anywhere inside a frame/window can only be reported after the whole
+    /// window arrived, so the worst case is the duration of a window. The estimate
+    /// is based on the latest window, because the window length is determined by
+    /// the audio callbacks. Buffering inside the audio driver and device comes on
+    /// top of this and is not known here.
+    #[inline(always)]
+    pub fn detection_latency_ms(&self) -> u32 {
+        (self.ms_per_sample * self.frame_len.get() as f32).ceil() as u32
+    }
+
+    /// Duration in ms of all audio that was analyzed so far. In contrast to
+    /// [`AnalysisState::time_ms`], this is calculated from the total amount of
+    /// samples and therefore doesn't accumulate rounding errors of single frames.
+    #[inline(always)]
+    pub fn elapsed_audio_time_ms(&self) -> u32 {
+        if !self.is_initialized() {
+            return 0;
+        }
+        (self.samples_processed() as u64 * 1000 / self.sampling_rate() as u64) as u32
+    }
 }
 
 #[cfg(test)]
@@ -162,4 +372,58 @@ mod tests {
             "Must return timestamp at end of third window"
         );
     }
+    #[test]
+    #[should_panic]
+    fn test_analysis_state_uninitialized_panics() {
+        let state = AnalysisState::uninitialized();
+        state.update_time(1024);
+    }
+
+    #[test]
+    fn test_analysis_state_set_sampling_rate_later() {
+        let mut state = AnalysisState::uninitialized();
+        assert!(!state.is_initialized());
+        state.set_sampling_rate(44100);
+        assert!(state.is_initialized());
+        state.update_time(1024);
+        assert_eq!(23, state.time_ms());
+    }
+
+    #[test]
+    fn test_analysis_state_samples_processed() {
+        let state = AnalysisState::new(44100);
+        assert_eq!(0, state.samples_processed());
+        (0..100).for_each(|_| state.update_time(441));
+        assert_eq!(44100, state.samples_processed());
+        assert_eq!(1000, state.elapsed_audio_time_ms());
+    }
+
+    #[test]
+    fn test_analysis_state_time_origin() {
+        let state = AnalysisState::new(1000);
+        state.update_time(1000);
+        assert_eq!(500, state.reported_beat_time_ms());
+        // new clip starts at 10s in the logical timeline
+        state.set_time_origin(10_000);
+        state.update_time(1000);
+        assert_eq!(10_500, state.reported_beat_time_ms());
+        assert_eq!(1500, state.beat_time_ms());
+        state.update_time(1000);
+        assert_eq!(11_500, state.reported_beat_time_ms());
+
+        // the time saturates instead of overflowing
+        state.set_time_origin(u32::MAX - 100);
+        state.update_time(1000);
+        assert_eq!(u32::MAX, state.reported_beat_time_ms());
+    }
+
+    #[test]
+    fn test_analysis_state_detection_latency() {
+        let state = AnalysisState::new(44100);
+        assert_eq!(0, state.detection_latency_ms());
+        state.update_time(1024);
+        assert_eq!(24, state.detection_latency_ms());
+        state.update_time(441);
+        assert_eq!(10, state.detection_latency_ms());
+    }
 }
@@ -23,18 +23,21 @@ SOFTWARE.
 */
 use crate::strategies::window_stats::WindowStats;
 use crate::strategies::AnalysisState;
-use crate::{BeatInfo, Strategy, StrategyKind};
+use crate::{AnalysisOutcome, BeatInfo, LastAnalysis, Strategy, StrategyKind};
 use spectrum_analyzer::FrequencyLimit;
 use ringbuffer::{ConstGenericRingBuffer, RingBufferWrite, RingBufferExt};
 use std::cell::RefCell;
 use spectrum_analyzer::scaling::divide_by_N;
 
+/// Highest frequency in Hz that is relevant for the analysis.
+pub(crate) const MAX_FREQUENCY_HZ: f32 = 90.0;
+
 /// Struct to provide a beat-detection strategy using a
 /// Spectrum Analysis. The algorithm is pretty basic/stupid.
 /// It's not smart enough to cope with 'complex' music, like
 /// most of today's pop. But it will give pretty good results
 /// in 'easy' music, like most of 90s pop hits.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SABeatDetector {
     state: AnalysisState,
     // ring buffer with latest audio; necessary because we don't
@@ -46,11 +49,20 @@ pub struct SABeatDetector {
 impl SABeatDetector {
     #[inline(always)]
     pub fn new(sampling_rate: u32) -> Self {
+        let mut detector = Self::uninitialized();
+        detector.state.set_sampling_rate(sampling_rate);
+        detector
+    }
+
+    /// Constructor for a detector whose sampling rate is set later
+    /// via [`Strategy::set_sampling_rate`].
+    #[inline(always)]
+    pub fn uninitialized() -> Self {
         const LEN: usize = 1024;
         let mut initial_buf = ConstGenericRingBuffer::<f32, LEN>::new();
         (0..LEN).for_each(|_| initial_buf.push(0.0));
         Self {
-            state: AnalysisState::new(sampling_rate),
+            state: AnalysisState::uninitialized(),
             audio_data_buf: RefCell::from(initial_buf),
         }
     }
@@ -60,6 +72,10 @@ impl Strategy for SABeatDetector {
     /// Callback called when the audio input library got the next callback.
     #[inline(always)]
     fn is_beat(&self, callback_samples: &[i16]) -> Option<BeatInfo> {
+        // nothing to analyze; some audio drivers deliver empty callbacks
+        if callback_samples.is_empty() {
+            return None;
+        }
         // make sure we have the latest 1024 audio samples in the buffer
         // => ready for FFT
         let mut audio_data_buf = self.audio_data_buf.borrow_mut();
@@ -69,30 +85,53 @@ impl Strategy for SABeatDetector {
 
         // tell the state beforehand that we are analyzing the next window - important!
         self.state.update_time(callback_samples.len());
+        self.state.update_level(callback_samples);
         // skip if distance to last beat is not far away enough
         if !self.last_beat_beyond_threshold(&self.state) {
+            self.state.set_last_analysis(LastAnalysis {
+                outcome: AnalysisOutcome::TooCloseToLastBeat,
+                ..LastAnalysis::NOT_ANALYZED
+            });
             return None;
         };
         // skip if the amplitude is too low, e.g. noise or silence between songs
         let w_stats = WindowStats::from(callback_samples);
         if !self.amplitude_high_enough(&w_stats) {
+            self.state.set_last_analysis(LastAnalysis {
+                outcome: AnalysisOutcome::AmplitudeTooLow,
+                intensity: Some(w_stats.intensity()),
+                ..LastAnalysis::NOT_ANALYZED
+            });
             return None;
         };
 
         let spectrum = spectrum_analyzer::samples_fft_to_spectrum(
             &audio_data_buf.to_vec(),
             self.state.sampling_rate(),
-            FrequencyLimit::Max(90.0),
+            FrequencyLimit::Max(MAX_FREQUENCY_HZ),
             // None,
             Some(&divide_by_N),
         ).unwrap();
 
         // I don't know what the value really means :D
         // figured out by testing.. :/
-        if spectrum.max().1.val() > 2_100_000.0 {
+        const THRESHOLD: f32 = 2_100_000.0;
+        let max_magnitude = spectrum.max().1.val();
+        let is_beat = max_magnitude > THRESHOLD;
+        self.state.set_last_analysis(LastAnalysis {
+            outcome: if is_beat { AnalysisOutcome::Beat } else { AnalysisOutcome::BelowCriteria },
+            intensity: Some(w_stats.intensity()),
+            criterion: Some(max_magnitude),
+            threshold: Some(THRESHOLD),
+        });
+        if is_beat {
             // mark we found a beat
             self.state.update_last_discovered_beat_timestamp();
-            Some(BeatInfo::new(self.state.beat_time_ms()))
+            Some(
+                BeatInfo::new(self.state.reported_beat_time_ms(), w_stats.intensity())
+                    .with_brightness(w_stats.brightness())
+                    .with_sample_index(self.state.beat_sample_index()),
+            )
         } else {
             None
         }
@@ -103,6 +142,20 @@ impl Strategy for SABeatDetector {
         StrategyKind::Spectrum
     }
 
+    #[inline(always)]
+    fn state(&self) -> &AnalysisState {
+        &self.state
+    }
+
+    #[inline(always)]
+    fn set_sampling_rate(&mut self, sampling_rate: u32) {
+        self.state.set_sampling_rate(sampling_rate)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Strategy + Send> {
+        Box::new(self.clone())
+    }
+
     fn name() -> &'static str
     where
         Self: Sized,
@@ -29,6 +29,8 @@ SOFTWARE.
 pub struct WindowStats {
     // the maximum amplitude inside a signed 16 bit sampled audio data window
     max: u16,
+    // zero crossings per sample pair, i.e. in range [0, 1]
+    zero_crossing_rate: f32,
 }
 
 impl WindowStats {
@@ -36,6 +38,22 @@ impl WindowStats {
     pub const fn max(&self) -> u16 {
         self.max
     }
+
+    /// The maximum amplitude normalized to range `[0, 1]`.
+    #[inline(always)]
+    pub fn intensity(&self) -> f32 {
+        self.max as f32 / i16::MAX as f32
+    }
+
+    /// Approximation of the spectral centroid in range `[0, 1]` via the zero
+    /// crossing rate, where `1` corresponds to the Nyquist frequency. A sine with
+    /// frequency `f` has a brightness of about `f / (sampling_rate / 2)`. Dark
+    /// hits like kicks have values close to `0`, bright hits like hi-hats
+    /// considerably higher values.
+    #[inline(always)]
+    pub const fn brightness(&self) -> f32 {
+        self.zero_crossing_rate
+    }
 }
 
 impl From<&[i16]> for WindowStats {
@@ -50,6 +68,51 @@ impl From<&[i16]> for WindowStats {
         abs_samples_ordered.sort_unstable();
         let max = *abs_samples_ordered.last().unwrap() as u16;
 
-        Self { max }
+        let zero_crossings = samples
+            .windows(2)
+            .filter(|pair| (pair[0] < 0) != (pair[1] < 0))
+            .count();
+        let zero_crossing_rate = if samples.len() > 1 {
+            zero_crossings as f32 / (samples.len() - 1) as f32
+        } else {
+            0.0
+        };
+
+        Self {
+            max,
+            zero_crossing_rate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn sine(frequency: f32, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| (2.0 * PI * frequency * i as f32 / 44100.0).sin())
+            .map(|x| (x * 0.8 * i16::MAX as f32) as i16)
+            .collect()
+    }
+
+    #[test]
+    fn test_window_stats_brightness() {
+        let low = WindowStats::from(sine(60.0, 1024).as_slice());
+        let high = WindowStats::from(sine(8000.0, 1024).as_slice());
+        assert!(
+            low.brightness() < 0.01,
+            "brightness is {}",
+            low.brightness()
+        );
+        // 8000Hz / 22050Hz
+        assert!(
+            (high.brightness() - 0.36).abs() < 0.02,
+            "brightness is {}",
+            high.brightness()
+        );
+        assert_eq!(0.0, WindowStats::from([1_i16].as_ref()).brightness());
+        assert_eq!(1.0, WindowStats::from([1_i16, -1, 1].as_ref()).brightness());
     }
 }
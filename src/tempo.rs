@@ -0,0 +1,827 @@
+/*
+MIT License
+
+Copyright (c) 2021 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for tempo (BPM) estimation on top of detected beats.
+
+use crate::BeatInfo;
+use std::collections::VecDeque;
+
+/// Default amount of beats a [`TempoTracker`] keeps in its history.
+const DEFAULT_HISTORY_LEN: usize = 10;
+/// Default weight of the previous estimate in the exponential smoothing of the
+/// tempo, i.e. a new interval has a weight of `1 - DEFAULT_RECENCY_DECAY`.
+const DEFAULT_RECENCY_DECAY: f32 = 0.75;
+/// Default amount of beats per bar for [`TempoTracker::beat_phase`], i.e. 4/4.
+const DEFAULT_BEATS_PER_BAR: u8 = 4;
+/// Weight of a new interval in the confidence update.
+const SMOOTHING_FACTOR: f32 = 0.25;
+/// Relative tolerance to classify an interval as half/double-time
+/// ("octave") error of the current estimate.
+const OCTAVE_ERROR_TOLERANCE: f32 = 0.15;
+/// Resolution of the onset signal for the autocorrelation in ms.
+const ONSET_BIN_MS: u32 = 10;
+/// Weights to smear an onset over its neighbouring bins. Beat times are
+/// only as precise as the analysis windows, i.e. about ±12ms.
+const ONSET_SMEAR: [f32; 3] = [1.0, 0.6, 0.2];
+/// Maximum time span of the history for the autocorrelation, which bounds the
+/// memory of the onset signal. 10 minutes are 60000 bins.
+const MAX_ONSET_SPAN_MS: u32 = 10 * 60 * 1000;
+/// Plausible tempo range for the autocorrelation.
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+
+/// Keeps a history of the latest beats and derives a smoothed tempo from them.
+///
+/// A naive BPM from the latest inter-beat interval jumps around because of timing
+/// jitter and missed beats. This tracker smooths the tempo exponentially and
+/// rejects intervals that are roughly half or double the current estimate,
+/// i.e. missed beats or spurious beats in between.
+///
+/// Feed it with every beat you get from a [`crate::Strategy`], e.g. in the
+/// callback of [`crate::record::start_listening`].
+#[derive(Debug)]
+pub struct TempoTracker {
+    /// Latest beats; oldest first.
+    beat_history: VecDeque<BeatInfo>,
+    /// BPM derived from the two latest beats.
+    instantaneous_bpm: Option<f32>,
+    /// Smoothed BPM.
+    bpm: Option<f32>,
+    /// Stability of the smoothed BPM in range `[0, 1]`.
+    confidence: f32,
+    /// Total amount of beats since the tracker was created.
+    beat_count: usize,
+    /// Maximum amount of beats in [`beat_history`].
+    history_len: usize,
+    /// See [`TempoTracker::with_recency_decay`].
+    recency_decay: f32,
+    /// See [`TempoTracker::with_beats_per_bar`].
+    beats_per_bar: u8,
+    /// See [`TempoTracker::with_relative_gate`].
+    relative_gate: f32,
+}
+
+/// Aggregated statistics over the beats of a [`TempoTracker`].
+#[derive(Debug, Clone, Copy)]
+pub struct BeatStats {
+    /// Total amount of beats since the tracker was created.
+    pub beat_count: usize,
+    /// Average intensity of the beats in the history. `0.0` if there are none.
+    pub average_intensity: f32,
+    /// Average interval between the beats in the history in ms.
+    pub average_interval_ms: Option<f32>,
+    /// Smoothed BPM, see [`TempoTracker::bpm`].
+    pub bpm: Option<f32>,
+}
+
+impl TempoTracker {
+    /// Constructor for [`TempoTracker`] that keeps the latest 10 beats.
+    pub fn new() -> Self {
+        Self::with_history_len(DEFAULT_HISTORY_LEN)
+    }
+
+    /// Constructor for [`TempoTracker`] that keeps the latest `history_len` beats.
+    /// A longer history makes [`TempoTracker::beat_grid`] and
+    /// [`TempoTracker::estimate_tempo_autocorr`] more robust but slower to follow
+    /// tempo changes. Panics if `history_len` is smaller than 2.
+    pub fn with_history_len(history_len: usize) -> Self {
+        assert!(
+            history_len >= 2,
+            "The history must keep at least two beats to derive a tempo!"
+        );
+        Self {
+            beat_history: VecDeque::with_capacity(history_len),
+            instantaneous_bpm: None,
+            bpm: None,
+            confidence: 0.0,
+            beat_count: 0,
+            history_len,
+            recency_decay: DEFAULT_RECENCY_DECAY,
+            beats_per_bar: DEFAULT_BEATS_PER_BAR,
+            relative_gate: 0.0,
+        }
+    }
+
+    /// Sets how fast the weight of older intervals decays in the smoothed tempo.
+    ///
+    /// The interval `n` beats ago has a weight proportional to `decay^n`. Smaller
+    /// values follow tempo changes faster, e.g. of a live drummer or a DJ
+    /// transition, larger values suppress jitter better. The default is `0.75`.
+    /// Intervals that look like missed or spurious beats are rejected regardless.
+    /// Panics if `decay` is not in range `[0, 1)`.
+    pub fn with_recency_decay(mut self, decay: f32) -> Self {
+        assert!(
+            (0.0..1.0).contains(&decay),
+            "The decay must be in range [0, 1)!"
+        );
+        self.recency_decay = decay;
+        self
+    }
+
+    /// Sets the amount of beats per bar for [`TempoTracker::beat_phase`], e.g. `3`
+    /// for a waltz. The default is `4`. Panics if `beats_per_bar` is `0`.
+    pub fn with_beats_per_bar(mut self, beats_per_bar: u8) -> Self {
+        assert!(beats_per_bar > 0, "A bar must have at least one beat!");
+        self.beats_per_bar = beats_per_bar;
+        self
+    }
+
+    /// Sets the percentile in range `[0, 1]` of the recent beat intensities that a
+    /// beat must reach to be significant, see [`TempoTracker::on_beat_gated`].
+    ///
+    /// E.g. `0.75` only keeps beats that are louder than 75% of the beats in the
+    /// history. Unlike a fixed threshold, this adapts to the dynamics of the
+    /// track, e.g. to thin out dense and loud material. The default is `0.0`,
+    /// i.e. no gating. Panics if `percentile` is not in range `[0, 1]`.
+    pub fn with_relative_gate(mut self, percentile: f32) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&percentile),
+            "The percentile must be in range [0, 1]!"
+        );
+        self.relative_gate = percentile;
+        self
+    }
+
+    /// Whether a new beat is significant compared to the intensities of the beats
+    /// in the history, see [`TempoTracker::with_relative_gate`]. The beat is
+    /// significant if the share of beats in the history with a lower intensity
+    /// is at least the gate. Every beat is significant if the history is empty.
+    pub fn is_significant(&self, info: &BeatInfo) -> bool {
+        if self.beat_history.is_empty() {
+            return true;
+        }
+        let quieter = self
+            .beat_history
+            .iter()
+            .filter(|beat| beat.intensity() < info.intensity())
+            .count();
+        quieter as f32 / self.beat_history.len() as f32 >= self.relative_gate
+    }
+
+    /// Like [`TempoTracker::on_beat`] but returns the beat only if it is
+    /// significant according to [`TempoTracker::is_significant`].
+    ///
+    /// Insignificant beats are still added to the history, so that they are part
+    /// of the tempo and of the intensity distribution.
+    pub fn on_beat_gated(&mut self, info: &BeatInfo) -> Option<BeatInfo> {
+        let is_significant = self.is_significant(info);
+        self.on_beat(info);
+        if is_significant {
+            Some(*info)
+        } else {
+            None
+        }
+    }
+
+    /// Adds a newly detected beat and updates the tempo estimation.
+    pub fn on_beat(&mut self, info: &BeatInfo) {
+        let previous = self.beat_history.back().map(|b| b.relative_ms());
+        if self.beat_history.len() == self.history_len {
+            self.beat_history.pop_front();
+        }
+        self.beat_history.push_back(*info);
+        self.beat_count += 1;
+
+        let interval_ms = match previous {
+            Some(previous) if info.relative_ms() > previous => info.relative_ms() - previous,
+            _ => return,
+        };
+        let instantaneous_bpm = 60_000.0 / interval_ms as f32;
+        self.instantaneous_bpm = Some(instantaneous_bpm);
+
+        let bpm = match self.bpm {
+            Some(bpm) => bpm,
+            None => {
+                self.bpm = Some(instantaneous_bpm);
+                return;
+            }
+        };
+
+        let ratio = instantaneous_bpm / bpm;
+        let is_octave_error = (ratio - 2.0).abs() < 2.0 * OCTAVE_ERROR_TOLERANCE
+            || (ratio - 0.5).abs() < 0.5 * OCTAVE_ERROR_TOLERANCE;
+        if is_octave_error {
+            // missed or spurious beat: keep the estimate but trust it a bit less
+            self.confidence *= 1.0 - SMOOTHING_FACTOR;
+            return;
+        }
+
+        self.bpm = Some(bpm + (1.0 - self.recency_decay) * (instantaneous_bpm - bpm));
+        // an interval that deviates 50% or more from the estimate means no stability at all
+        let stability = (1.0 - (ratio - 1.0).abs() * 2.0).max(0.0);
+        self.confidence += SMOOTHING_FACTOR * (stability - self.confidence);
+    }
+
+    /// Getter for the maximum amount of beats in the history.
+    #[inline(always)]
+    pub const fn history_len(&self) -> usize {
+        self.history_len
+    }
+
+    /// Getter for the BPM derived from the interval between the two latest beats.
+    #[inline(always)]
+    pub const fn instantaneous_bpm(&self) -> Option<f32> {
+        self.instantaneous_bpm
+    }
+
+    /// Getter for the smoothed BPM. `None` until at least two beats were found.
+    #[inline(always)]
+    pub const fn bpm(&self) -> Option<f32> {
+        self.bpm
+    }
+
+    /// Predicted time of the next beat in ms: the latest beat plus one beat period
+    /// of the smoothed tempo. Together with the current audio time in the same
+    /// time base as the beats, e.g. [`crate::Strategy::elapsed_audio_time_ms`]
+    /// if no time origin was set, this gives the time until the next beat, e.g.
+    /// to ease into a flash. `None` until a tempo is established.
+    pub fn next_beat_ms(&self) -> Option<f32> {
+        let latest = self.beat_history.back()?.relative_ms() as f32;
+        Some(latest + 60_000.0 / self.bpm?)
+    }
+
+    /// Fits the beats of the history to a regular grid `offset + n * period`
+    /// via least squares. Returns the tempo of the grid in BPM and its phase
+    /// offset in ms, which is in range `[0, period)`. Consumers can use this to
+    /// generate evenly spaced markers instead of the raw, jittery beat times.
+    /// `None` until a tempo is established.
+    pub fn beat_grid(&self) -> Option<(f32, f32)> {
+        self.fit_grid(self.beat_history.len())
+            .map(|(period, offset)| (60_000.0 / period, offset))
+    }
+
+    /// Signed deviation in ms of the latest beat from the grid predicted by the
+    /// beats before it (see [`TempoTracker::beat_grid`]). Negative values mean
+    /// the beat came early ("rushing"), positive values mean it came late
+    /// ("dragging"). `None` until a grid can be fitted to the previous beats.
+    pub fn beat_timing_error(&self) -> Option<f32> {
+        let latest = self.beat_history.back()?.relative_ms() as f32;
+        let (period, offset) = self.fit_grid(self.beat_history.len() - 1)?;
+        let slot = ((latest - offset) / period).round();
+        Some(latest - (offset + slot * period))
+    }
+
+    /// Least squares fit of the first `len` beats of the history to a grid.
+    /// Returns the period and the phase offset in ms.
+    fn fit_grid(&self, len: usize) -> Option<(f32, f32)> {
+        let period_estimate = 60_000.0 / self.bpm?;
+        let first = self.beat_history.front()?.relative_ms();
+
+        // assign each beat to its slot on the grid; missed beats leave gaps
+        let points = self
+            .beat_history
+            .iter()
+            .take(len)
+            .map(|b| b.relative_ms() as f32)
+            .map(|ms| (((ms - first as f32) / period_estimate).round(), ms))
+            .collect::<Vec<_>>();
+        let count = points.len() as f32;
+        let mean_n = points.iter().map(|(n, _)| n).sum::<f32>() / count;
+        let mean_ms = points.iter().map(|(_, ms)| ms).sum::<f32>() / count;
+        let variance_n = points
+            .iter()
+            .map(|(n, _)| (n - mean_n) * (n - mean_n))
+            .sum::<f32>();
+        if variance_n == 0.0 {
+            return None;
+        }
+        let covariance = points
+            .iter()
+            .map(|(n, ms)| (n - mean_n) * (ms - mean_ms))
+            .sum::<f32>();
+        let period = covariance / variance_n;
+        let offset = (mean_ms - period * mean_n).rem_euclid(period);
+        Some((period, offset))
+    }
+
+    /// Estimates a global tempo by autocorrelating an onset signal built from the
+    /// beats in the history, weighted by their intensity.
+    ///
+    /// In contrast to [`TempoTracker::bpm`], which is derived from inter-beat
+    /// intervals, this copes well with missed or spurious beats. The result is
+    /// in range `[60, 200]` BPM. `None` if no two beats in the history are one
+    /// period of a tempo in that range apart (e.g. fewer than two beats or a
+    /// span shorter than the 300ms of 200 BPM), or if the history spans more
+    /// than 10 minutes.
+    pub fn estimate_tempo_autocorr(&self) -> Option<f32> {
+        // beat times aren't necessarily monotonic, e.g. after the time origin of
+        // the detector was moved back
+        let first = self.recent_beats().map(|b| b.relative_ms()).min()?;
+        let last = self.recent_beats().map(|b| b.relative_ms()).max()?;
+        if last - first > MAX_ONSET_SPAN_MS {
+            return None;
+        }
+        let len = ((last - first) / ONSET_BIN_MS) as usize + ONSET_SMEAR.len();
+
+        let mut onsets = vec![0.0; len];
+        for beat in &self.beat_history {
+            let bin = ((beat.relative_ms() - first) / ONSET_BIN_MS) as usize;
+            // smear each onset to tolerate timing jitter
+            for (distance, weight) in ONSET_SMEAR.iter().enumerate() {
+                onsets[bin + distance] += weight * beat.intensity();
+                if distance > 0 && bin >= distance {
+                    onsets[bin - distance] += weight * beat.intensity();
+                }
+            }
+        }
+
+        let min_lag = (60_000.0 / MAX_BPM / ONSET_BIN_MS as f32).round() as usize;
+        let max_lag = (60_000.0 / MIN_BPM / ONSET_BIN_MS as f32).round() as usize;
+        (min_lag..=max_lag.min(len - 1))
+            .map(|lag| {
+                let correlation = onsets
+                    .iter()
+                    .zip(&onsets[lag..])
+                    .map(|(a, b)| a * b)
+                    .sum::<f32>();
+                // log-gaussian tempo prior around 120 BPM; resolves the ambiguity
+                // between a period and its multiples in favour of common tempos
+                let bpm = 60_000.0 / (lag as u32 * ONSET_BIN_MS) as f32;
+                let octaves = (bpm / 120.0).log2();
+                (lag, correlation * (-0.5 * octaves * octaves).exp())
+            })
+            .filter(|(_, correlation)| *correlation > 0.0)
+            .fold(None, |best, (lag, correlation)| match best {
+                Some((_, best_correlation)) if best_correlation >= correlation => best,
+                _ => Some((lag, correlation)),
+            })
+            .map(|(lag, _)| 60_000.0 / (lag as u32 * ONSET_BIN_MS) as f32)
+    }
+
+    /// Histogram of the inter-onset intervals between all pairs of beats in the
+    /// history, not only neighbours. Bin `i` counts the intervals in range
+    /// `[i, i + 1) * max_interval_ms / bins`; longer intervals are ignored. The
+    /// dominant bin indicates the beat period; smaller peaks reveal subdivisions
+    /// and multiples of it.
+    pub fn ioi_histogram(&self, bins: usize, max_interval_ms: f32) -> Vec<u32> {
+        let mut histogram = vec![0; bins];
+        let bin_width = max_interval_ms / bins as f32;
+        for (i, earlier) in self.beat_history.iter().enumerate() {
+            for later in self.beat_history.iter().skip(i + 1) {
+                let interval = later.relative_ms().saturating_sub(earlier.relative_ms()) as f32;
+                let bin = (interval / bin_width) as usize;
+                if let Some(count) = histogram.get_mut(bin) {
+                    *count += 1;
+                }
+            }
+        }
+        histogram
+    }
+
+    /// The beat with the highest intensity in the history, e.g. to emphasize big
+    /// hits or a drop. If several beats have the same intensity, the latest one
+    /// is returned.
+    pub fn strongest_recent_beat(&self) -> Option<BeatInfo> {
+        self.recent_beats()
+            .fold(None, |strongest: Option<BeatInfo>, beat| match strongest {
+                Some(strongest) if strongest.intensity() > beat.intensity() => Some(strongest),
+                _ => Some(beat),
+            })
+    }
+
+    /// Position of the latest beat in its bar as `(beat in bar, beats per bar)`,
+    /// where `0` is the downbeat, e.g. for effects that are synced to bars.
+    ///
+    /// This is a heuristic: downbeats tend to be accented, so the downbeat is the
+    /// position in the bar whose beats in the history are the loudest on average.
+    /// It needs at least one full bar in the history and gets out of step when a
+    /// beat is missed. Returns `None` if there are not enough beats yet.
+    pub fn beat_phase(&self) -> Option<(u8, u8)> {
+        let beats_per_bar = self.beats_per_bar as usize;
+        if self.beat_history.len() < beats_per_bar {
+            return None;
+        }
+        // count beats since the tracker was created, so that the phase doesn't
+        // shift when old beats drop out of the history
+        let first_index = self.beat_count - self.beat_history.len();
+        let mut sums = vec![0.0; beats_per_bar];
+        let mut counts = vec![0; beats_per_bar];
+        for (i, beat) in self.beat_history.iter().enumerate() {
+            let position = (first_index + i) % beats_per_bar;
+            sums[position] += beat.intensity();
+            counts[position] += 1;
+        }
+        // the first position wins on ties
+        let downbeat = (0..beats_per_bar)
+            .map(|position| sums[position] / counts[position] as f32)
+            .enumerate()
+            .fold((0, f32::MIN), |best, (position, average)| {
+                if average > best.1 {
+                    (position, average)
+                } else {
+                    best
+                }
+            })
+            .0;
+        let latest = (self.beat_count - 1) % beats_per_bar;
+        let beat_in_bar = (latest + beats_per_bar - downbeat) % beats_per_bar;
+        Some((beat_in_bar as u8, self.beats_per_bar))
+    }
+
+    /// Iterates over the beats in the history, oldest first.
+    pub fn recent_beats(&self) -> impl Iterator<Item = BeatInfo> + '_ {
+        self.beat_history.iter().copied()
+    }
+
+    /// Aggregated statistics over the beats in the history.
+    pub fn stats(&self) -> BeatStats {
+        let len = self.beat_history.len();
+        let average_intensity = if len == 0 {
+            0.0
+        } else {
+            self.beat_history.iter().map(|b| b.intensity()).sum::<f32>() / len as f32
+        };
+        let average_interval_ms = match (self.beat_history.front(), self.beat_history.back()) {
+            (Some(first), Some(last)) if len > 1 => {
+                // saturate: beat times go backwards if the time origin was moved back
+                let span_ms = last.relative_ms().saturating_sub(first.relative_ms());
+                Some(span_ms as f32 / (len - 1) as f32)
+            }
+            _ => None,
+        };
+        BeatStats {
+            beat_count: self.beat_count,
+            average_intensity,
+            average_interval_ms,
+            bpm: self.bpm,
+        }
+    }
+
+    /// Getter for the stability of the smoothed BPM in range `[0, 1]`.
+    /// The value grows with every interval that matches the current estimate.
+    #[inline(always)]
+    pub const fn confidence(&self) -> f32 {
+        self.confidence
+    }
+}
+
+impl Default for TempoTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::synth_beats;
+    use crate::StrategyKind;
+
+    #[test]
+    fn test_tempo_tracker_steady_with_missed_beat() {
+        let mut tracker = TempoTracker::new();
+        assert_eq!(None, tracker.bpm());
+
+        // 120 BPM => 500ms between beats; beat at 3000ms is missing
+        (0..20)
+            .map(|i| i * 500)
+            .filter(|ms| *ms != 3000)
+            .for_each(|ms| tracker.on_beat(&BeatInfo::new(ms, 1.0)));
+
+        let bpm = tracker.bpm().unwrap();
+        assert!(
+            (bpm - 120.0).abs() < 0.5,
+            "must stay at ~120 BPM, is {}",
+            bpm
+        );
+        assert_eq!(Some(120.0), tracker.instantaneous_bpm());
+        assert!(
+            tracker.confidence() > 0.8,
+            "confidence is {}",
+            tracker.confidence()
+        );
+    }
+
+    #[test]
+    fn test_tempo_tracker_smooths_jitter() {
+        let mut tracker = TempoTracker::new();
+        let mut ms = 0;
+        for i in 0..20 {
+            tracker.on_beat(&BeatInfo::new(ms, 1.0));
+            ms += if i % 2 == 0 { 480 } else { 520 };
+        }
+        let bpm = tracker.bpm().unwrap();
+        assert!(
+            (bpm - 120.0).abs() < 3.0,
+            "must be close to 120 BPM, is {}",
+            bpm
+        );
+    }
+
+    #[test]
+    fn test_tempo_tracker_beat_grid() {
+        let mut tracker = TempoTracker::new();
+        assert_eq!(None, tracker.beat_grid());
+
+        // 120 BPM grid with an offset of 130ms and some jitter
+        let jitter = [0, 12, -8, 5, -11, 9, -3, 7, -6, 10, -9, 4];
+        jitter
+            .iter()
+            .enumerate()
+            .map(|(n, j)| (1130 + n as i32 * 500 + j) as u32)
+            .for_each(|ms| tracker.on_beat(&BeatInfo::new(ms, 1.0)));
+
+        let (bpm, offset_ms) = tracker.beat_grid().unwrap();
+        assert!((bpm - 120.0).abs() < 0.5, "bpm is {}", bpm);
+        assert!(
+            (offset_ms - 130.0).abs() < 10.0,
+            "offset is {}ms",
+            offset_ms
+        );
+    }
+
+    #[test]
+    fn test_tempo_tracker_beat_timing_error() {
+        let mut tracker = TempoTracker::new();
+        assert_eq!(None, tracker.beat_timing_error());
+
+        (0..8)
+            .map(|n| 1130 + n * 500)
+            .for_each(|ms| tracker.on_beat(&BeatInfo::new(ms, 1.0)));
+        let error = tracker.beat_timing_error().unwrap();
+        assert!(error.abs() < 1.0, "error is {}ms", error);
+
+        // slightly ahead of the grid
+        tracker.on_beat(&BeatInfo::new(1130 + 8 * 500 - 30, 1.0));
+        let error = tracker.beat_timing_error().unwrap();
+        assert!((error + 30.0).abs() < 1.0, "error is {}ms", error);
+
+        // behind the grid
+        tracker.on_beat(&BeatInfo::new(1130 + 9 * 500 + 20, 1.0));
+        let error = tracker.beat_timing_error().unwrap();
+        assert!(error > 10.0, "error is {}ms", error);
+    }
+
+    #[test]
+    fn test_tempo_tracker_history_len() {
+        let mut tracker = TempoTracker::with_history_len(64);
+        assert_eq!(64, tracker.history_len());
+        (1..=100).for_each(|n| tracker.on_beat(&BeatInfo::new(n * 500, 1.0)));
+        assert_eq!(64, tracker.recent_beats().count());
+        assert_eq!(
+            37 * 500,
+            tracker.recent_beats().next().unwrap().relative_ms()
+        );
+        assert_eq!(100, tracker.stats().beat_count);
+        let bpm = tracker.estimate_tempo_autocorr().unwrap();
+        assert!((bpm - 120.0).abs() < 2.0, "bpm is {}", bpm);
+
+        assert_eq!(10, TempoTracker::new().history_len());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_tempo_tracker_history_len_too_short() {
+        let _ = TempoTracker::with_history_len(1);
+    }
+
+    #[test]
+    fn test_tempo_tracker_recency_decay() {
+        let mut default = TempoTracker::new();
+        let mut responsive = TempoTracker::new().with_recency_decay(0.4);
+        let mut ms = 0;
+        let mut on_beat = |ms| {
+            default.on_beat(&BeatInfo::new(ms, 1.0));
+            responsive.on_beat(&BeatInfo::new(ms, 1.0));
+        };
+        (0..10).for_each(|_| {
+            ms += 500;
+            on_beat(ms)
+        });
+        // tempo steps from 120 to 140 BPM
+        (0..4).for_each(|_| {
+            ms += 429;
+            on_beat(ms)
+        });
+
+        let flat_average = 60_000.0 / default.stats().average_interval_ms.unwrap();
+        let default_bpm = default.bpm().unwrap();
+        let responsive_bpm = responsive.bpm().unwrap();
+        assert!(flat_average < 130.0, "flat average is {}", flat_average);
+        assert!(default_bpm > flat_average, "bpm is {}", default_bpm);
+        assert!(responsive_bpm > default_bpm, "bpm is {}", responsive_bpm);
+        assert!(
+            (responsive_bpm - 140.0).abs() < 1.0,
+            "bpm is {}",
+            responsive_bpm
+        );
+    }
+
+    #[test]
+    fn test_tempo_tracker_ioi_histogram() {
+        let mut tracker = TempoTracker::new();
+        assert_eq!(vec![0; 20], tracker.ioi_histogram(20, 2000.0));
+
+        // period of 520ms with some jitter
+        let jitter = [0, 5, -4, 3, -5, 4, -2, 3, -3, 5];
+        jitter
+            .iter()
+            .enumerate()
+            .map(|(n, j)| (1000 + n as i32 * 520 + j) as u32)
+            .for_each(|ms| tracker.on_beat(&BeatInfo::new(ms, 1.0)));
+
+        // bins of 100ms
+        let histogram = tracker.ioi_histogram(20, 2000.0);
+        let dominant_bin = (0..histogram.len()).max_by_key(|i| histogram[*i]).unwrap();
+        assert_eq!(5, dominant_bin);
+        assert_eq!(9, histogram[5]);
+        // multiples of the period
+        assert_eq!(8, histogram[10]);
+        assert_eq!(7, histogram[15]);
+        assert_eq!(24, histogram.iter().sum::<u32>());
+    }
+
+    #[test]
+    fn test_tempo_tracker_strongest_recent_beat() {
+        let mut tracker = TempoTracker::with_history_len(4);
+        assert!(tracker.strongest_recent_beat().is_none());
+
+        [0.3, 0.9, 0.5, 0.9, 0.2]
+            .iter()
+            .enumerate()
+            .for_each(|(n, intensity)| tracker.on_beat(&BeatInfo::new(n as u32 * 500, *intensity)));
+        let strongest = tracker.strongest_recent_beat().unwrap();
+        assert_eq!(0.9, strongest.intensity());
+        assert_eq!(1500, strongest.relative_ms());
+
+        // the strongest beats drop out of the history
+        tracker.on_beat(&BeatInfo::new(2500, 0.1));
+        tracker.on_beat(&BeatInfo::new(3000, 0.4));
+        tracker.on_beat(&BeatInfo::new(3500, 0.1));
+        assert_eq!(3000, tracker.strongest_recent_beat().unwrap().relative_ms());
+    }
+
+    #[test]
+    fn test_tempo_tracker_beat_phase() {
+        let mut tracker = TempoTracker::new();
+        // the song starts with a soft beat; the loud beats are the downbeats
+        let accents = [0.3, 0.3, 0.9, 0.3, 0.3, 0.3, 0.9, 0.3, 0.3, 0.3, 0.9, 0.3];
+        for (n, intensity) in accents.iter().enumerate() {
+            tracker.on_beat(&BeatInfo::new(n as u32 * 500, *intensity));
+            if n < 3 {
+                assert!(tracker.beat_phase().is_none());
+                continue;
+            }
+            let (beat_in_bar, beats_per_bar) = tracker.beat_phase().unwrap();
+            assert_eq!(4, beats_per_bar);
+            assert_eq!((n + 2) % 4, beat_in_bar as usize, "beat {}", n);
+            if *intensity > 0.5 {
+                assert_eq!(0, beat_in_bar);
+            }
+        }
+
+        let mut tracker = TempoTracker::new().with_beats_per_bar(3);
+        [0.9, 0.3, 0.3, 0.9, 0.3]
+            .iter()
+            .enumerate()
+            .for_each(|(n, intensity)| tracker.on_beat(&BeatInfo::new(n as u32 * 500, *intensity)));
+        assert_eq!(Some((1, 3)), tracker.beat_phase());
+    }
+
+    #[test]
+    fn test_tempo_tracker_relative_gate() {
+        // dense material: a beat every 250ms with varying intensity
+        let beats = (0..64)
+            .map(|n| BeatInfo::new(n * 250, [0.6, 0.7, 0.65, 0.9, 0.62, 0.75][n as usize % 6]))
+            .collect::<Vec<_>>();
+        let count_reported = |gate| {
+            let mut tracker = TempoTracker::new().with_relative_gate(gate);
+            beats
+                .iter()
+                .filter_map(|beat| tracker.on_beat_gated(beat))
+                .count()
+        };
+        let ungated = count_reported(0.0);
+        let gated = count_reported(0.5);
+        let strongly_gated = count_reported(0.75);
+        assert_eq!(beats.len(), ungated);
+        assert!(gated < ungated, "{} < {}", gated, ungated);
+        assert!(strongly_gated < gated, "{} < {}", strongly_gated, gated);
+        // the loudest beats always pass
+        assert!(strongly_gated >= beats.len() / 6);
+    }
+
+    #[test]
+    fn test_tempo_tracker_next_beat_ms() {
+        let mut tracker = TempoTracker::new();
+        assert_eq!(None, tracker.next_beat_ms());
+        tracker.on_beat(&BeatInfo::new(1000, 1.0));
+        assert_eq!(None, tracker.next_beat_ms());
+
+        let jitter = [12, -8, 5, -11, 9, -3, 7];
+        jitter
+            .iter()
+            .enumerate()
+            .map(|(n, j)| (1500 + n as i32 * 500 + j) as u32)
+            .for_each(|ms| tracker.on_beat(&BeatInfo::new(ms, 1.0)));
+        // the actual next beat is at 5000ms
+        let next = tracker.next_beat_ms().unwrap();
+        assert!((next - 5000.0).abs() < 20.0, "next beat at {}ms", next);
+    }
+
+    #[test]
+    fn test_tempo_tracker_stats() {
+        let mut tracker = TempoTracker::new();
+        let stats = tracker.stats();
+        assert_eq!(0, stats.beat_count);
+        assert_eq!(None, stats.average_interval_ms);
+
+        // more beats than the history can hold
+        (0..15).for_each(|i| {
+            let intensity = if i % 2 == 0 { 0.4 } else { 0.8 };
+            tracker.on_beat(&BeatInfo::new(i * 500, intensity))
+        });
+        let stats = tracker.stats();
+        assert_eq!(15, stats.beat_count);
+        assert!((stats.average_intensity - 0.6).abs() < 0.001);
+        assert_eq!(Some(500.0), stats.average_interval_ms);
+        assert_eq!(Some(120.0), stats.bpm);
+    }
+
+    #[test]
+    fn test_tempo_tracker_stats_non_monotonic() {
+        let mut tracker = TempoTracker::new();
+        tracker.on_beat(&BeatInfo::new(5000, 1.0));
+        tracker.on_beat(&BeatInfo::new(1000, 1.0));
+        assert_eq!(Some(0.0), tracker.stats().average_interval_ms);
+    }
+
+    #[test]
+    fn test_tempo_tracker_recent_beats() {
+        let mut tracker = TempoTracker::new();
+        assert_eq!(0, tracker.recent_beats().count());
+
+        (0..15).for_each(|i| tracker.on_beat(&BeatInfo::new(i * 500, 1.0)));
+        let times = tracker
+            .recent_beats()
+            .map(|b| b.relative_ms())
+            .collect::<Vec<_>>();
+        assert_eq!((5..15).map(|i| i * 500).collect::<Vec<_>>(), times);
+    }
+
+    #[test]
+    fn test_tempo_tracker_estimate_tempo_autocorr() {
+        let mut tracker = TempoTracker::new();
+        assert_eq!(None, tracker.estimate_tempo_autocorr());
+        tracker.on_beat(&BeatInfo::new(1000, 1.0));
+        assert_eq!(None, tracker.estimate_tempo_autocorr());
+        // a span of less than one beat period of the slowest tempo is enough
+        tracker.on_beat(&BeatInfo::new(1500, 1.0));
+        assert_eq!(Some(120.0), tracker.estimate_tempo_autocorr());
+
+        let mut tracker = TempoTracker::new();
+        let detector = StrategyKind::LPF.detector(44100);
+        synth_beats(44100, 120.0, 10, 50.0)
+            .chunks(1024)
+            .filter_map(|window| detector.is_beat(window))
+            .for_each(|beat| tracker.on_beat(&beat));
+
+        let bpm = tracker.estimate_tempo_autocorr().unwrap();
+        assert!((bpm - 120.0).abs() < 3.0, "bpm is {}", bpm);
+    }
+
+    #[test]
+    fn test_tempo_tracker_estimate_tempo_autocorr_missed_beats() {
+        let mut tracker = TempoTracker::new();
+        // 100 BPM => 600ms; two beats are missing
+        (1..14)
+            .map(|i| i * 600)
+            .filter(|ms| *ms != 3000 && *ms != 4800)
+            .for_each(|ms| tracker.on_beat(&BeatInfo::new(ms, 1.0)));
+        assert_eq!(Some(100.0), tracker.estimate_tempo_autocorr());
+    }
+
+    #[test]
+    fn test_tempo_tracker_estimate_tempo_autocorr_non_monotonic() {
+        let mut tracker = TempoTracker::new();
+        // the time jumps back, e.g. because the time origin was reset
+        [5000, 5500, 6000, 1000, 1500, 2000]
+            .iter()
+            .for_each(|ms| tracker.on_beat(&BeatInfo::new(*ms, 1.0)));
+        assert!(tracker.estimate_tempo_autocorr().is_some());
+
+        // huge jumps don't allocate a huge onset signal
+        tracker.on_beat(&BeatInfo::new(u32::MAX, 1.0));
+        assert_eq!(None, tracker.estimate_tempo_autocorr());
+    }
+}
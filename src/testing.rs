@@ -0,0 +1,82 @@
+/*
+MIT License
+
+Copyright (c) 2021 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module with helpers to test beat detection without binary audio fixtures.
+//! Only available with the `testing` feature.
+
+use std::f32::consts::PI;
+
+/// Peak amplitude of each hit in range `[0, 1]`.
+const HIT_AMPLITUDE: f32 = 0.9;
+/// Time constant of the exponential decay of each hit in seconds.
+const HIT_DECAY_S: f32 = 0.04;
+
+/// Generates mono audio data with `n_beats` clean hits at a known tempo.
+///
+/// Each hit
+/// is a sine with frequency `fundamental_hz` that decays exponentially. The first
+/// hit is one beat period after the beginning and the data ends one beat period
+/// after the last hit, e.g. 120 BPM results in hits at 500ms, 1000ms, ...
+pub fn synth_beats(sampling_rate: u32, bpm: f32, n_beats: usize, fundamental_hz: f32) -> Vec<i16> {
+    let period_samples = (60.0 / bpm * sampling_rate as f32) as usize;
+    (0..(n_beats + 1) * period_samples)
+        .map(|i| {
+            let beat = i / period_samples;
+            if beat == 0 {
+                return 0;
+            }
+            let t = (i % period_samples) as f32 / sampling_rate as f32;
+            let amplitude = HIT_AMPLITUDE * (-t / HIT_DECAY_S).exp();
+            (amplitude * (2.0 * PI * fundamental_hz * t).sin() * i16::MAX as f32) as i16
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StrategyKind;
+
+    #[test]
+    fn test_synth_beats_detected() {
+        let samples = synth_beats(44100, 120.0, 8, 50.0);
+        assert_eq!(9 * 22050, samples.len());
+
+        // the threshold of the spectrum strategy is tuned for real music
+        let detector = StrategyKind::LPF.detector(44100);
+        let beats = samples
+            .chunks(1024)
+            .filter_map(|window| detector.is_beat(window))
+            .collect::<Vec<_>>();
+        assert_eq!(8, beats.len(), "must detect all beats");
+        for (i, beat) in beats.iter().enumerate() {
+            let expected_ms = (i as i64 + 1) * 500;
+            assert!(
+                (beat.relative_ms() as i64 - expected_ms).abs() < 50,
+                "beat {} at {}ms",
+                i,
+                beat.relative_ms()
+            );
+        }
+    }
+}
@@ -0,0 +1,110 @@
+/*
+MIT License
+
+Copyright (c) 2021 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Bindings for WebAssembly.
+//!
+//! They enable the beat detection from JavaScript, e.g. inside an
+//! `AudioWorkletProcessor`. This module is independent from
+//! [`crate::record`], which uses `cpal` and can't record audio in the browser.
+//! The audio must be passed in by the JavaScript side instead.
+//!
+//! `wasm-bindgen` needs a newer Rust version than the MSRV of this crate, so
+//! the `wasm` feature does as well.
+
+// the code generated by `wasm-bindgen` uses items newer than the MSRV
+#![allow(clippy::incompatible_msrv)]
+
+use crate::{BeatInfo, Strategy, StrategyKind};
+use wasm_bindgen::prelude::*;
+
+/// Beat detector that can be used from JavaScript. It uses the
+/// [`StrategyKind::LPF`] strategy.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct WasmBeatDetector {
+    detector: Box<dyn Strategy + Send>,
+}
+
+#[wasm_bindgen]
+impl WasmBeatDetector {
+    /// Constructor. `sample_rate` is usually `AudioContext.sampleRate`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            detector: StrategyKind::LPF.detector(sample_rate),
+        }
+    }
+
+    /// Analyzes the next mono audio samples, i.e. the content of a `Float32Array`
+    /// in range `[-1, 1]`. Returns a [`WasmBeatInfo`] object if a beat was found,
+    /// otherwise `null`.
+    pub fn on_new_audio(&mut self, samples: &[f32]) -> JsValue {
+        self.detector
+            .is_beat_f32(samples)
+            .map(|info| JsValue::from(WasmBeatInfo::from(info)))
+            .unwrap_or(JsValue::NULL)
+    }
+}
+
+/// JavaScript view of [`BeatInfo`].
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct WasmBeatInfo {
+    relative_ms: u32,
+    intensity: f32,
+    brightness: f32,
+}
+
+// wasm_bindgen can't export const functions
+#[allow(clippy::missing_const_for_fn)]
+#[wasm_bindgen]
+impl WasmBeatInfo {
+    /// See [`BeatInfo::relative_ms`].
+    #[wasm_bindgen(getter, js_name = relativeMs)]
+    pub fn relative_ms(&self) -> u32 {
+        self.relative_ms
+    }
+
+    /// See [`BeatInfo::intensity`].
+    #[wasm_bindgen(getter)]
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// See [`BeatInfo::brightness`].
+    #[wasm_bindgen(getter)]
+    pub fn brightness(&self) -> f32 {
+        self.brightness
+    }
+}
+
+impl From<BeatInfo> for WasmBeatInfo {
+    fn from(info: BeatInfo) -> Self {
+        Self {
+            relative_ms: info.relative_ms(),
+            intensity: info.intensity(),
+            brightness: info.brightness(),
+        }
+    }
+}